@@ -0,0 +1,27 @@
+//! Prints `test-vectors.json`, see the `vectors` module.
+
+use opaque_borink::suite::{P256Argon2, P384Argon2, Ristretto255Argon2};
+use opaque_borink::vectors::generate;
+
+fn main() {
+    let vectors = [
+        generate::<Ristretto255Argon2>(),
+        generate::<P256Argon2>(),
+        generate::<P384Argon2>(),
+    ];
+
+    println!("{{");
+    println!("  \"description\": \"Registration and login of every suite with seeded random number generators. All bytes are base64url-encoded without padding. The login_server_state ends with its creation time, fixed at 2026-01-01T00:00:00Z, and a seeded nonce.\",");
+    println!("  \"vectors\": [");
+    for (i, vector) in vectors.iter().enumerate() {
+        println!("    {{");
+        for (j, (name, value)) in vector.iter().enumerate() {
+            let comma = if j + 1 < vector.len() { "," } else { "" };
+            println!("      \"{}\": \"{}\"{}", name, value, comma);
+        }
+        let comma = if i + 1 < vectors.len() { "," } else { "" };
+        println!("    }}{}", comma);
+    }
+    println!("  ]");
+    println!("}}");
+}
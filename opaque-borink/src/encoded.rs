@@ -1,5 +1,7 @@
 use base64::{engine::general_purpose as b64, Engine as _};
 
+use crate::secret::SecretBytes;
+use crate::suite::{Suite, SuiteId};
 use crate::Error;
 
 pub fn encode_bytes(bytes: &[u8]) -> String {
@@ -9,3 +11,208 @@ pub fn encode_bytes(bytes: &[u8]) -> String {
 pub fn decode_string(s: &str) -> Result<Vec<u8>, Error> {
     Ok(b64::URL_SAFE_NO_PAD.decode(s)?)
 }
+
+/// [`decode_string`] for secrets, such as serialized setups and states, which are zeroized on
+/// drop.
+pub fn decode_secret(s: &str) -> Result<SecretBytes, Error> {
+    Ok(decode_string(s)?.into())
+}
+
+pub const ENVELOPE_MAGIC: [u8; 4] = *b"OPQB";
+pub const ENVELOPE_VERSION: u8 = 1;
+/// Magic, format version, object kind and suite id.
+pub const ENVELOPE_HEADER_LEN: usize = 7;
+
+/// The type of object stored in an envelope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ObjectKind {
+    ServerSetup = 1,
+    PasswordFile = 2,
+    ServerLoginState = 3,
+    ClientLoginState = 4,
+    ClientRegistrationState = 5,
+    RegistrationRequest = 6,
+    RegistrationResponse = 7,
+    RegistrationUpload = 8,
+    CredentialRequest = 9,
+    CredentialResponse = 10,
+    CredentialFinalization = 11,
+    ServerSetupRing = 12,
+    ClientChangePasswordState = 13,
+    ChangePasswordRequest = 14,
+    ChangePasswordResponse = 15,
+    ChangePasswordFinalization = 16,
+}
+
+impl TryFrom<u8> for ObjectKind {
+    type Error = Error;
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        Ok(match kind {
+            1 => ObjectKind::ServerSetup,
+            2 => ObjectKind::PasswordFile,
+            3 => ObjectKind::ServerLoginState,
+            4 => ObjectKind::ClientLoginState,
+            5 => ObjectKind::ClientRegistrationState,
+            6 => ObjectKind::RegistrationRequest,
+            7 => ObjectKind::RegistrationResponse,
+            8 => ObjectKind::RegistrationUpload,
+            9 => ObjectKind::CredentialRequest,
+            10 => ObjectKind::CredentialResponse,
+            11 => ObjectKind::CredentialFinalization,
+            12 => ObjectKind::ServerSetupRing,
+            13 => ObjectKind::ClientChangePasswordState,
+            14 => ObjectKind::ChangePasswordRequest,
+            15 => ObjectKind::ChangePasswordResponse,
+            16 => ObjectKind::ChangePasswordFinalization,
+            _ => {
+                return Err(Error::InvalidEnvelope(format!(
+                    "unknown object kind {:#04x}",
+                    kind
+                )))
+            }
+        })
+    }
+}
+
+/// A decoded envelope. The payload is exactly what the `serialize` method of the object returns,
+/// or the message as returned in the protocol results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Envelope<'a> {
+    pub version: u8,
+    pub kind: ObjectKind,
+    pub suite: SuiteId,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    /// Returns the payload if the envelope contains an object of `kind` belonging to `CS`.
+    pub fn payload_for<CS: Suite>(&self, kind: ObjectKind) -> Result<&'a [u8], Error> {
+        if self.kind != kind {
+            return Err(Error::WrongObjectKind {
+                expected: kind,
+                found: self.kind,
+            });
+        }
+        if self.suite != CS::ID {
+            return Err(Error::SuiteMismatch {
+                expected: CS::ID,
+                found: self.suite,
+            });
+        }
+
+        Ok(self.payload)
+    }
+}
+
+/// Wraps a serialized object or message in a self-describing envelope, so that stored bytes can be
+/// identified without knowing where they came from. All deserializers accept both envelopes and
+/// the bare serialized form.
+pub fn encode_envelope(kind: ObjectKind, suite: SuiteId, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(ENVELOPE_HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&ENVELOPE_MAGIC);
+    bytes.push(ENVELOPE_VERSION);
+    bytes.push(kind as u8);
+    bytes.push(suite as u8);
+    bytes.extend_from_slice(payload);
+
+    bytes
+}
+
+pub fn is_envelope(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ENVELOPE_MAGIC)
+}
+
+pub fn decode_envelope(bytes: &[u8]) -> Result<Envelope<'_>, Error> {
+    if !is_envelope(bytes) || bytes.len() < ENVELOPE_HEADER_LEN {
+        return Err(Error::InvalidEnvelope("missing envelope header".to_owned()));
+    }
+    let version = bytes[4];
+    if version != ENVELOPE_VERSION {
+        return Err(Error::InvalidEnvelope(format!(
+            "unsupported format version {}",
+            version
+        )));
+    }
+
+    Ok(Envelope {
+        version,
+        kind: ObjectKind::try_from(bytes[5])?,
+        suite: SuiteId::try_from(bytes[6])?,
+        payload: &bytes[ENVELOPE_HEADER_LEN..],
+    })
+}
+
+/// Opens `bytes` if it is an envelope, checking its kind and suite. Other input is returned as is.
+///
+/// `legacy_lens` are the lengths of the bare forms of the default suite. Bare bytes can start with
+/// the envelope magic by chance, so input of these lengths is never opened, like in [`untag`].
+/// Envelopes are always longer than their bare form, so no envelope is missed.
+///
+/// [`untag`]: crate::suite::untag
+pub(crate) fn open_envelope<'a, CS: Suite>(
+    bytes: &'a [u8],
+    kind: ObjectKind,
+    legacy_lens: &[usize],
+) -> Result<&'a [u8], Error> {
+    if CS::ID == SuiteId::Ristretto255Argon2 && legacy_lens.contains(&bytes.len()) {
+        return Ok(bytes);
+    }
+    if is_envelope(bytes) {
+        decode_envelope(bytes)?.payload_for::<CS>(kind)
+    } else {
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::suite::{P256Argon2, Ristretto255Argon2};
+
+    use super::*;
+
+    #[test]
+    fn envelope_roundtrip() {
+        let bytes = encode_envelope(ObjectKind::PasswordFile, SuiteId::P256Argon2, &[1, 2, 3]);
+        let envelope = decode_envelope(&bytes).unwrap();
+
+        assert_eq!(ENVELOPE_VERSION, envelope.version);
+        assert_eq!(ObjectKind::PasswordFile, envelope.kind);
+        assert_eq!(SuiteId::P256Argon2, envelope.suite);
+        assert_eq!(&[1, 2, 3], envelope.payload);
+        assert_eq!(
+            &[1, 2, 3],
+            open_envelope::<P256Argon2>(&bytes, ObjectKind::PasswordFile, &[]).unwrap()
+        );
+    }
+
+    #[test]
+    fn envelope_checks() {
+        let bytes = encode_envelope(ObjectKind::PasswordFile, SuiteId::P256Argon2, &[1, 2, 3]);
+
+        assert!(matches!(
+            open_envelope::<P256Argon2>(&bytes, ObjectKind::ServerSetup, &[]),
+            Err(Error::WrongObjectKind {
+                expected: ObjectKind::ServerSetup,
+                found: ObjectKind::PasswordFile
+            })
+        ));
+        assert!(matches!(
+            open_envelope::<Ristretto255Argon2>(&bytes, ObjectKind::PasswordFile, &[]),
+            Err(Error::SuiteMismatch { .. })
+        ));
+
+        let mut future = bytes.clone();
+        future[4] = ENVELOPE_VERSION + 1;
+        assert!(matches!(
+            decode_envelope(&future),
+            Err(Error::InvalidEnvelope(_))
+        ));
+        assert!(decode_envelope(&[1, 2, 3]).is_err());
+        assert_eq!(
+            &[1, 2, 3],
+            open_envelope::<P256Argon2>(&[1, 2, 3], ObjectKind::PasswordFile, &[]).unwrap()
+        );
+    }
+}
@@ -0,0 +1,174 @@
+pub use argon2::Algorithm;
+use argon2::{Argon2, Params, Version};
+use opaque_ke::errors::ProtocolError;
+
+use crate::Error;
+
+/// Length of the serialized form of [`KsfParams`]: one byte for the algorithm, followed by the
+/// memory, time and parallelism costs as big-endian u32's.
+pub const KSF_PARAMS_LEN: usize = 13;
+
+/// Maximum memory cost in KiB (256 MiB). The parameters are chosen by the client and stored in the
+/// password file, so without a bound a registration could make every later login run out of
+/// memory.
+pub const MAX_M_COST: u32 = 256 * 1024;
+/// Maximum number of iterations.
+pub const MAX_T_COST: u32 = 16;
+/// Maximum degree of parallelism.
+pub const MAX_P_COST: u32 = 16;
+
+/// Parameters of the Argon2 key stretching function. The same parameters must be used during
+/// registration and login, which is why they are recorded in the registration state, the
+/// registration upload and the password file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KsfParams {
+    pub algorithm: Algorithm,
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for KsfParams {
+    /// The defaults of the `argon2` crate, which is what all password files before `KsfParams`
+    /// existed were created with.
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::default(),
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KsfParams {
+    /// Fails with `Error::KsfCostTooHigh` if a cost exceeds [`MAX_M_COST`], [`MAX_T_COST`] or
+    /// [`MAX_P_COST`].
+    pub fn new(algorithm: Algorithm, m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Self, Error> {
+        let params = Self {
+            algorithm,
+            m_cost,
+            t_cost,
+            p_cost,
+        };
+        // Fail early on parameters Argon2 does not accept
+        params.to_ksf()?;
+
+        Ok(params)
+    }
+
+    pub(crate) fn to_ksf(self) -> Result<Argon2<'static>, Error> {
+        if self.m_cost > MAX_M_COST || self.t_cost > MAX_T_COST || self.p_cost > MAX_P_COST {
+            return Err(Error::KsfCostTooHigh);
+        }
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, None)?;
+
+        Ok(Argon2::new(self.algorithm, Version::V0x13, params))
+    }
+
+    pub fn serialize(&self) -> [u8; KSF_PARAMS_LEN] {
+        let mut bytes = [0u8; KSF_PARAMS_LEN];
+        bytes[0] = match self.algorithm {
+            Algorithm::Argon2d => 0,
+            Algorithm::Argon2i => 1,
+            Algorithm::Argon2id => 2,
+        };
+        bytes[1..5].copy_from_slice(&self.m_cost.to_be_bytes());
+        bytes[5..9].copy_from_slice(&self.t_cost.to_be_bytes());
+        bytes[9..13].copy_from_slice(&self.p_cost.to_be_bytes());
+
+        bytes
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != KSF_PARAMS_LEN {
+            return Err(ProtocolError::SerializationError.into());
+        }
+        let algorithm = match bytes[0] {
+            0 => Algorithm::Argon2d,
+            1 => Algorithm::Argon2i,
+            2 => Algorithm::Argon2id,
+            _ => return Err(ProtocolError::SerializationError.into()),
+        };
+        let read_u32 =
+            |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        Self::new(algorithm, read_u32(1), read_u32(5), read_u32(9))
+    }
+
+    /// Splits `bytes` into the part before the trailing serialized parameters and the parameters
+    /// themselves. Input of exactly `legacy_len` bytes predates `KsfParams` and gets the defaults.
+    pub(crate) fn split_trailing(
+        bytes: &[u8],
+        legacy_len: Option<usize>,
+    ) -> Result<(&[u8], Self), Error> {
+        if Some(bytes.len()) == legacy_len {
+            return Ok((bytes, Self::default()));
+        }
+        if bytes.len() < KSF_PARAMS_LEN {
+            return Err(ProtocolError::SerializationError.into());
+        }
+        let (rest, params) = bytes.split_at(bytes.len() - KSF_PARAMS_LEN);
+
+        Ok((rest, Self::deserialize(params)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_matches_argon2_default() {
+        let ksf = KsfParams::default().to_ksf().unwrap();
+        let default = Argon2::default();
+
+        assert_eq!(ksf.params().m_cost(), default.params().m_cost());
+        assert_eq!(ksf.params().t_cost(), default.params().t_cost());
+        assert_eq!(ksf.params().p_cost(), default.params().p_cost());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let params = KsfParams::new(Algorithm::Argon2i, 8192, 3, 2).unwrap();
+        let bytes = params.serialize();
+
+        assert_eq!(params, KsfParams::deserialize(&bytes).unwrap());
+    }
+
+    #[test]
+    fn invalid_params() {
+        assert!(KsfParams::new(Algorithm::Argon2id, 1, 1, 1).is_err());
+
+        let mut bytes = KsfParams::default().serialize();
+        bytes[0] = 3;
+        assert!(KsfParams::deserialize(&bytes).is_err());
+    }
+
+    #[test]
+    fn cost_too_high() {
+        assert!(KsfParams::new(Algorithm::Argon2id, MAX_M_COST, MAX_T_COST, MAX_P_COST).is_ok());
+        for (m_cost, t_cost, p_cost) in [
+            (MAX_M_COST + 1, 2, 1),
+            (19 * 1024, MAX_T_COST + 1, 1),
+            (19 * 1024, 2, MAX_P_COST + 1),
+            (u32::MAX, u32::MAX, 1),
+        ] {
+            assert!(matches!(
+                KsfParams::new(Algorithm::Argon2id, m_cost, t_cost, p_cost),
+                Err(Error::KsfCostTooHigh)
+            ));
+
+            // Also when sent by a client along with its registration upload
+            let mut bytes = vec![0u8; 64];
+            bytes.push(2);
+            bytes.extend_from_slice(&m_cost.to_be_bytes());
+            bytes.extend_from_slice(&t_cost.to_be_bytes());
+            bytes.extend_from_slice(&p_cost.to_be_bytes());
+            assert!(matches!(
+                KsfParams::split_trailing(&bytes, None),
+                Err(Error::KsfCostTooHigh)
+            ));
+        }
+    }
+}
@@ -1,33 +1,225 @@
 pub mod client;
 pub mod encoded;
+pub mod ksf;
+pub mod replay;
+pub mod ring;
+pub mod rng;
+pub mod sealed;
+pub mod secret;
 pub mod server;
+pub mod session;
+pub mod suite;
+pub mod typed;
+#[cfg(any(test, feature = "test-vectors"))]
+pub mod vectors;
 
 use base64::DecodeError;
-use opaque_ke::ciphersuite::CipherSuite;
+use encoded::ObjectKind;
+use opaque_ke::errors::InternalError;
 pub use opaque_ke::errors::ProtocolError;
+/// Optional identities of the client and server, which are bound into the registration envelope
+/// and the key exchange. When not set, opaque-ke uses the respective public keys.
+pub use opaque_ke::Identifiers;
 use std::fmt::{Debug, Display, Formatter};
+use suite::SuiteId;
 
-pub struct Cipher;
-impl CipherSuite for Cipher {
-    type OprfCs = opaque_ke::Ristretto255;
-    type KeGroup = opaque_ke::Ristretto255;
-    type KeyExchange = opaque_ke::key_exchange::tripledh::TripleDh;
-    type Ksf = argon2::Argon2<'static>;
-}
+/// The default suite.
+pub use suite::Ristretto255Argon2 as Cipher;
 
 #[derive(Debug)]
 pub enum Error {
     ProtocolError(ProtocolError),
     DecodeError(DecodeError),
+    KsfError(argon2::Error),
+    /// A cost of the KSF parameters exceeds `ksf::MAX_M_COST`, `ksf::MAX_T_COST` or
+    /// `ksf::MAX_P_COST`.
+    KsfCostTooHigh,
+    UnknownSuite(String),
+    SuiteMismatch {
+        expected: SuiteId,
+        found: SuiteId,
+    },
+    WrongObjectKind {
+        expected: ObjectKind,
+        found: ObjectKind,
+    },
+    InvalidEnvelope(String),
+    /// A step was called on a state that is not in the right stage, e.g. finishing a login
+    /// before starting it.
+    InvalidState(&'static str),
+    /// A password file refers to a key id that is not in the `ServerSetupRing`, or a setup is
+    /// removed while it is current.
+    UnknownKeyId(u32),
+    /// A setup is added to a `ServerSetupRing` under a key id that is already in use.
+    DuplicateKeyId(u32),
+    /// The server used a different public key than the one expected by the client.
+    ServerPublicKeyMismatch,
+    /// A key longer than `session::MAX_DERIVED_KEY_LEN` is requested from `session::derive_key`.
+    InvalidKeyLength(usize),
+    /// A sealed login state was sealed with another key or for another user, or was modified.
+    InvalidSealedState,
+    /// A login state is past its expiry time or older than the maximum age.
+    StateExpired,
+    /// A login state was already used to finish a login.
+    StateReplayed,
+    /// The `replay::SeenNonces` store failed.
+    NonceStore(String),
+}
+
+/// Broad classification of an [`Error`], for deciding how to respond to it. The codes and names
+/// are stable and are also used by the bindings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The password is wrong, the identities or context do not match those of the other side, or
+    /// the server public key is not the expected one.
+    InvalidLogin,
+    /// A message, state or other serialized object has the wrong length or content.
+    MalformedMessage,
+    /// A serialized object is of a different kind or suite than expected.
+    WrongObjectKind,
+    InvalidBase64,
+    /// A step was called out of order, or its state has expired.
+    InvalidState,
+    /// Invalid KSF parameters, an unknown suite name or another argument out of range.
+    InvalidParameters,
+    /// A failure inside opaque-ke that is not caused by the input.
+    Internal,
+}
+
+impl ErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::InvalidLogin => "invalid_login",
+            ErrorKind::MalformedMessage => "malformed_message",
+            ErrorKind::WrongObjectKind => "wrong_object_kind",
+            ErrorKind::InvalidBase64 => "invalid_base64",
+            ErrorKind::InvalidState => "invalid_state",
+            ErrorKind::InvalidParameters => "invalid_parameters",
+            ErrorKind::Internal => "internal",
+        }
+    }
+
+    /// Name of the error class in the bindings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::InvalidLogin => "InvalidLogin",
+            ErrorKind::MalformedMessage => "SerializationError",
+            ErrorKind::WrongObjectKind => "WrongObjectKind",
+            ErrorKind::InvalidBase64 => "DecodeError",
+            ErrorKind::InvalidState => "InvalidState",
+            ErrorKind::InvalidParameters => "InvalidParameters",
+            ErrorKind::Internal => "InternalError",
+        }
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::ProtocolError(e) => match e {
+                ProtocolError::InvalidLoginError => ErrorKind::InvalidLogin,
+                ProtocolError::SerializationError
+                | ProtocolError::LibraryError(InternalError::SizeError { .. })
+                | ProtocolError::ReflectedValueError
+                | ProtocolError::IdentityGroupElementError => ErrorKind::MalformedMessage,
+                _ => ErrorKind::Internal,
+            },
+            Error::DecodeError(_) => ErrorKind::InvalidBase64,
+            Error::KsfError(_)
+            | Error::KsfCostTooHigh
+            | Error::UnknownSuite(_)
+            | Error::UnknownKeyId(_)
+            | Error::DuplicateKeyId(_)
+            | Error::InvalidKeyLength(_) => ErrorKind::InvalidParameters,
+            Error::SuiteMismatch { .. } | Error::WrongObjectKind { .. } => {
+                ErrorKind::WrongObjectKind
+            }
+            Error::InvalidEnvelope(_) => ErrorKind::MalformedMessage,
+            Error::InvalidState(_) => ErrorKind::InvalidState,
+            Error::ServerPublicKeyMismatch | Error::InvalidSealedState => ErrorKind::InvalidLogin,
+            Error::StateExpired | Error::StateReplayed => ErrorKind::InvalidState,
+            Error::NonceStore(_) => ErrorKind::Internal,
+        }
+    }
+
+    /// Shorthand for `self.kind().code()`.
+    pub fn code(&self) -> &'static str {
+        self.kind().code()
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", &self)
+        match self {
+            Error::ProtocolError(ProtocolError::InvalidLoginError) => {
+                f.write_str("invalid login, the password or the identities and context are wrong")
+            }
+            Error::ProtocolError(ProtocolError::SerializationError) => {
+                f.write_str("malformed message, it has the wrong length or content")
+            }
+            Error::ProtocolError(ProtocolError::LibraryError(InternalError::SizeError {
+                name,
+                len,
+                actual_len,
+            })) => write!(
+                f,
+                "malformed message, expected {} bytes for {}, found {}",
+                len, name, actual_len
+            ),
+            Error::ProtocolError(e) => write!(f, "protocol error: {:?}", e),
+            Error::DecodeError(e) => write!(f, "invalid base64url: {}", e),
+            Error::KsfError(e) => write!(f, "invalid KSF parameters: {}", e),
+            Error::KsfCostTooHigh => write!(
+                f,
+                "KSF costs too high, the maximums are {} KiB memory, {} iterations and {} lanes",
+                ksf::MAX_M_COST,
+                ksf::MAX_T_COST,
+                ksf::MAX_P_COST
+            ),
+            Error::UnknownSuite(suite) => write!(f, "unknown cipher suite {}", suite),
+            Error::SuiteMismatch { expected, found } => write!(
+                f,
+                "expected an object of cipher suite {}, found {}",
+                expected, found
+            ),
+            Error::WrongObjectKind { expected, found } => {
+                write!(f, "expected a {:?}, found a {:?}", expected, found)
+            }
+            Error::InvalidEnvelope(reason) => write!(f, "invalid envelope: {}", reason),
+            Error::InvalidState(reason) => write!(f, "invalid state: {}", reason),
+            Error::UnknownKeyId(key_id) => write!(f, "no server setup with key id {}", key_id),
+            Error::DuplicateKeyId(key_id) => {
+                write!(f, "a server setup with key id {} already exists", key_id)
+            }
+            Error::ServerPublicKeyMismatch => {
+                f.write_str("the server public key does not match the expected one")
+            }
+            Error::InvalidKeyLength(len) => write!(
+                f,
+                "cannot derive a key of {} bytes, the maximum is {}",
+                len,
+                session::MAX_DERIVED_KEY_LEN
+            ),
+            Error::InvalidSealedState => f.write_str(
+                "the sealed login state was sealed with another key or user id, or was modified",
+            ),
+            Error::StateExpired => f.write_str("the login state has expired"),
+            Error::StateReplayed => f.write_str("the login state has already been used"),
+            Error::NonceStore(reason) => write!(f, "seen nonce store failed: {}", reason),
+        }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ProtocolError(e) => Some(e),
+            Error::DecodeError(e) => Some(e),
+            Error::KsfError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl From<ProtocolError> for Error {
     fn from(e: ProtocolError) -> Self {
@@ -41,318 +233,892 @@ impl From<DecodeError> for Error {
     }
 }
 
+impl From<argon2::Error> for Error {
+    fn from(e: argon2::Error) -> Self {
+        Error::KsfError(e)
+    }
+}
+
 pub(crate) mod opaque_impl {
+    use hmac::{Hmac, Mac};
+    use opaque_ke::errors::ProtocolError;
     use opaque_ke::{
         ClientLogin, ClientLoginFinishParameters, ClientRegistration,
         ClientRegistrationFinishParameters, CredentialFinalization, CredentialRequest,
-        CredentialResponse, RegistrationRequest, RegistrationResponse, RegistrationUpload,
-        ServerLogin, ServerLoginStartParameters, ServerRegistration,
-    };
-    use rand::{
-        rngs::{OsRng, ThreadRng},
-        thread_rng,
+        CredentialResponse, Identifiers, RegistrationRequest, RegistrationResponse,
+        RegistrationUpload, ServerLogin, ServerLoginStartParameters, ServerRegistration,
     };
+    use rand::rngs::OsRng;
+    #[cfg(any(test, feature = "test-vectors"))]
+    use rand::{CryptoRng, RngCore};
+    use sha2::Sha512;
+
+    use super::encoded::{encode_envelope, open_envelope, ObjectKind};
+    use super::ksf::{KsfParams, KSF_PARAMS_LEN};
+    use super::replay::{state_meta, unix_time, StateChecks, STATE_META_LEN};
+    use super::ring::KeyId;
+    #[cfg(any(test, feature = "test-vectors"))]
+    use super::rng::CryptoRngCore;
+    use super::rng::StateRng;
+    use super::secret::SecretBytes;
+    use super::suite::{tag, untag, untag_key_id, Suite, SuiteId, KEY_ID_FLAG, SUITE_TAG_LEN};
+    use super::{Cipher, Error};
+
+    pub struct PasswordFile<CS: Suite = Cipher> {
+        registration: ServerRegistration<CS>,
+        ksf: KsfParams,
+        key_id: Option<KeyId>,
+    }
 
-    use super::{Cipher, Error, IntoArray};
-
-    #[repr(transparent)]
-    pub struct PasswordFile(ServerRegistration<Cipher>);
+    impl<CS: Suite> PasswordFile<CS> {
+        /// Password files with a key id are marked by a flag on the suite tag, those without one
+        /// are serialized as before key ids existed.
+        pub fn serialize(&self) -> Vec<u8> {
+            let registration = CS::serialize_server_registration(&self.registration);
+            let ksf = self.ksf.serialize();
+
+            match self.key_id {
+                Some(key_id) => {
+                    let mut bytes =
+                        tag::<CS>(&[&key_id.to_be_bytes(), registration.as_slice(), &ksf]);
+                    bytes[0] |= KEY_ID_FLAG;
+                    bytes
+                }
+                None => tag::<CS>(&[registration.as_slice(), &ksf]),
+            }
+        }
 
-    impl PasswordFile {
-        pub fn serialize(&self) -> [u8; PASSWORD_FILE_SERIALIZED_LEN] {
-            self.0.serialize().into_array()
+        pub fn serialize_envelope(&self) -> Vec<u8> {
+            encode_envelope(ObjectKind::PasswordFile, CS::ID, &self.serialize())
         }
 
+        /// Also accepts envelopes and untagged password files of the default suite serialized
+        /// before the KSF parameters were recorded, which were all created using the default
+        /// parameters.
         pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
-            let registration = opaque_ke::ServerRegistration::<Cipher>::deserialize(bytes)?;
+            let bytes = open_envelope::<CS>(
+                bytes,
+                ObjectKind::PasswordFile,
+                &[PASSWORD_FILE_REGISTRATION_LEN],
+            )?;
+            let (bytes, legacy, key_id) =
+                untag_key_id::<CS>(bytes, PASSWORD_FILE_REGISTRATION_LEN)?;
+            let (registration, ksf) =
+                KsfParams::split_trailing(bytes, legacy.then_some(bytes.len()))?;
+            let registration = ServerRegistration::<CS>::deserialize(registration)?;
+
+            Ok(Self {
+                registration,
+                ksf,
+                key_id,
+            })
+        }
+
+        /// Key id of the setup in the `ServerSetupRing` this password file was registered with.
+        pub fn key_id(&self) -> Option<KeyId> {
+            self.key_id
+        }
+
+        /// Records the key id of the setup used for `server_register_start`, see
+        /// `ServerSetupRing`.
+        pub fn set_key_id(&mut self, key_id: Option<KeyId>) {
+            self.key_id = key_id;
+        }
 
-            Ok(Self(registration))
+        /// The KSF parameters the client used during registration. The client must use the same
+        /// parameters when calling `client_login_finish`.
+        pub fn ksf_params(&self) -> KsfParams {
+            self.ksf
         }
     }
 
-    pub struct ServerSetup(opaque_ke::ServerSetup<Cipher>);
+    pub struct ServerSetup<CS: Suite = Cipher>(opaque_ke::ServerSetup<CS>);
 
-    impl ServerSetup {
+    impl<CS: Suite> ServerSetup<CS> {
         pub fn create() -> Self {
             let mut rng = OsRng;
-            let server_setup = opaque_ke::ServerSetup::<Cipher>::new(&mut rng);
+            let server_setup = opaque_ke::ServerSetup::<CS>::new(&mut rng);
 
             Self(server_setup)
         }
 
-        pub fn view(&self) -> ServerSetupView {
+        /// Creates the setup using `rng` instead of the OS generator, e.g. a seeded one for
+        /// reproducing test vectors.
+        #[cfg(any(test, feature = "test-vectors"))]
+        pub fn create_with_rng<R: CryptoRng + RngCore>(rng: &mut R) -> Self {
+            Self(opaque_ke::ServerSetup::<CS>::new(rng))
+        }
+
+        /// Static public key of the server, which clients can pin using the
+        /// `expected_server_public_key` of `client_login_finish`.
+        pub fn public_key(&self) -> Vec<u8> {
+            self.0.keypair().public().serialize().to_vec()
+        }
+
+        pub fn view(&self) -> ServerSetupView<'_, CS> {
             ServerSetupView {
                 setup: &self.0,
-                rng: thread_rng(),
+                rng: StateRng::default(),
+                now: None,
             }
         }
 
-        pub fn serialize(&self) -> [u8; SERVER_SETUP_LEN] {
-            self.0.serialize().into_array()
+        /// A view using `rng` instead of the thread-local generator, see
+        /// [`ServerSetup::create_with_rng`].
+        #[cfg(any(test, feature = "test-vectors"))]
+        pub fn view_with_rng<R: CryptoRngCore + 'static>(&self, rng: R) -> ServerSetupView<'_, CS> {
+            ServerSetupView {
+                setup: &self.0,
+                rng: StateRng::custom(rng),
+                now: None,
+            }
+        }
+
+        /// Contains the private keys of the server.
+        pub fn serialize(&self) -> SecretBytes {
+            tag::<CS>(&[CS::serialize_setup(&self.0).as_slice()]).into()
         }
 
+        pub fn serialize_envelope(&self) -> SecretBytes {
+            encode_envelope(ObjectKind::ServerSetup, CS::ID, &self.serialize()).into()
+        }
+
+        /// Also accepts envelopes and untagged setups of the default suite.
         pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
-            let setup = opaque_ke::ServerSetup::<Cipher>::deserialize(bytes)?;
+            let bytes = open_envelope::<CS>(bytes, ObjectKind::ServerSetup, &[SERVER_SETUP_LEN])?;
+            let (bytes, _) = untag::<CS>(bytes, SERVER_SETUP_LEN)?;
+            let setup = opaque_ke::ServerSetup::<CS>::deserialize(bytes)?;
 
             Ok(Self(setup))
         }
     }
 
-    #[derive(Clone)]
-    pub struct ServerSetupView<'a> {
-        setup: &'a opaque_ke::ServerSetup<Cipher>,
-        rng: rand::rngs::ThreadRng,
+    pub struct ServerSetupView<'a, CS: Suite = Cipher> {
+        setup: &'a opaque_ke::ServerSetup<CS>,
+        rng: StateRng,
+        /// Creation time of the login states, the current time if `None`.
+        now: Option<u64>,
     }
 
-    pub fn server_login_start(
-        setup: &mut ServerSetupView,
-        password_file: &PasswordFile,
+    impl<CS: Suite> ServerSetupView<'_, CS> {
+        /// Creates login states at the fixed `unix_time` instead of the current time, so that
+        /// together with [`ServerSetup::view_with_rng`] the states are reproducible.
+        #[cfg(any(test, feature = "test-vectors"))]
+        pub fn at_time(mut self, unix_time: u64) -> Self {
+            self.now = Some(unix_time);
+            self
+        }
+    }
+
+    impl<CS: Suite> Clone for ServerSetupView<'_, CS> {
+        fn clone(&self) -> Self {
+            Self {
+                setup: self.setup,
+                rng: self.rng.clone(),
+                now: self.now,
+            }
+        }
+    }
+
+    /// The `identifiers` must match those the client used during registration and login. The
+    /// `context` (e.g. the name of the application) must match the one used by the client, so a
+    /// transcript of one application can't be used with another sharing the same setup.
+    ///
+    /// Pass `None` as the `password_file` for users that do not exist. The response is then
+    /// indistinguishable from a real one, and the login only fails when it is finished, so that
+    /// the existence of accounts is not revealed.
+    pub fn server_login_start<CS: Suite>(
+        setup: &mut ServerSetupView<CS>,
+        password_file: Option<&PasswordFile<CS>>,
         login_start_request: &[u8],
         user_id: &str,
+        identifiers: Identifiers,
+        context: Option<&[u8]>,
     ) -> Result<ServerLoginStartResult, Error> {
-        let login_start_request = CredentialRequest::<Cipher>::deserialize(login_start_request)?;
+        let login_start_request = open_envelope::<CS>(
+            login_start_request,
+            ObjectKind::CredentialRequest,
+            &[LOGIN_CLIENT_MESSAGE_LEN],
+        )?;
+        let login_start_request = CredentialRequest::<CS>::deserialize(login_start_request)?;
 
-        let result = ServerLogin::<Cipher>::start(
+        let result = CS::server_login_start(
             &mut setup.rng,
             setup.setup,
-            Some(password_file.0.clone()),
+            password_file.map(|password_file| password_file.registration.clone()),
             login_start_request,
             user_id.as_bytes(),
-            ServerLoginStartParameters::default(),
+            ServerLoginStartParameters {
+                context,
+                identifiers,
+            },
         )?;
 
+        let meta = state_meta(&mut setup.rng, setup.now.unwrap_or_else(unix_time));
+
         Ok(ServerLoginStartResult {
-            response: result.message.serialize().into_array(),
-            state: result.state.serialize().into_array(),
+            response: CS::serialize_credential_response(&result.message),
+            state: tag::<CS>(&[result.state.serialize().as_slice(), &meta]).into(),
         })
     }
 
-    pub struct ClientStateLogin {
-        rng: rand::rngs::ThreadRng,
-        state: Option<ClientLogin<Cipher>>,
+    pub struct ClientStateLogin<CS: Suite = Cipher> {
+        rng: StateRng,
+        state: Option<ClientLogin<CS>>,
     }
 
-    pub fn client_login_start(
-        client_state: &mut ClientStateLogin,
+    pub fn client_login_start<CS: Suite>(
+        client_state: &mut ClientStateLogin<CS>,
         password: &[u8],
     ) -> Result<ClientLoginStartResult, Error> {
-        let result = ClientLogin::<Cipher>::start(&mut client_state.rng, password)?;
+        let result = ClientLogin::<CS>::start(&mut client_state.rng, password)?;
 
         client_state.state = Some(result.state);
 
         Ok(ClientLoginStartResult {
-            response: result.message.serialize().into_array(),
+            response: CS::serialize_credential_request(&result.message),
         })
     }
 
-    pub fn client_login_finish(
-        client_state: &mut ClientStateLogin,
+    /// The `identifiers` must match those used when calling `client_register_finish`, the
+    /// `context` must match the one passed to `server_login_start`.
+    ///
+    /// When `expected_server_public_key` is given, e.g. the `ServerSetup::public_key` published by
+    /// the server, the login fails with `Error::ServerPublicKeyMismatch` if the server uses a
+    /// different key.
+    pub fn client_login_finish<CS: Suite>(
+        client_state: &mut ClientStateLogin<CS>,
         password: &[u8],
         server_message: &[u8],
+        ksf_params: KsfParams,
+        identifiers: Identifiers,
+        context: Option<&[u8]>,
+        expected_server_public_key: Option<&[u8]>,
     ) -> Result<ClientLoginFinishResult, Error> {
-        if client_state.state.is_none() {
-            panic!("Client state not initialized! Run `client_login_start` first!")
-        }
-        let client_state = client_state.state.take().unwrap();
-        let server_message = CredentialResponse::<Cipher>::deserialize(server_message)?;
-        let result = client_state.finish(
+        let ksf = ksf_params.to_ksf()?;
+        let client_state = client_state.state.take().ok_or(Error::InvalidState(
+            "client state not initialized, run `client_login_start` first",
+        ))?;
+        let server_message = open_envelope::<CS>(
+            server_message,
+            ObjectKind::CredentialResponse,
+            &[LOGIN_SERVER_MESSAGE_LEN],
+        )?;
+        let server_message = CredentialResponse::<CS>::deserialize(server_message)?;
+        let result = CS::client_login_finish(
+            client_state,
             password,
             server_message,
-            ClientLoginFinishParameters::default(),
+            ClientLoginFinishParameters::new(context, identifiers, Some(&ksf)),
         )?;
+        let server_public_key = result.server_s_pk.serialize().to_vec();
+        if expected_server_public_key.is_some_and(|expected| expected != server_public_key) {
+            return Err(Error::ServerPublicKeyMismatch);
+        }
 
         Ok(ClientLoginFinishResult {
-            response: result.message.serialize().into_array(),
-            shared_secret: result.session_key.into_array(),
+            response: result.message.serialize().to_vec(),
+            shared_secret: result.session_key.to_vec().into(),
+            export_key: result.export_key.to_vec().into(),
+            server_public_key,
         })
     }
 
-    pub fn server_login_finish(
+    /// Also accepts envelopes and untagged states of the default suite. The `checks` can limit the
+    /// age of the state and reject states that were used before, see `replay::StateChecks`.
+    /// Untagged states have no creation time, so they are rejected unless the checks are empty.
+    pub fn server_login_finish<CS: Suite>(
         login_finish_request: &[u8],
         server_state: &[u8],
+        checks: &StateChecks,
     ) -> Result<ServerLoginFinishResult, Error> {
-        let state = ServerLogin::<Cipher>::deserialize(server_state)?;
-        let login_finish_request =
-            CredentialFinalization::<Cipher>::deserialize(login_finish_request)?;
+        let server_state = open_envelope::<CS>(
+            server_state,
+            ObjectKind::ServerLoginState,
+            &[LOGIN_SERVER_STATE_LEN],
+        )?;
+        let (server_state, legacy) = untag::<CS>(server_state, LOGIN_SERVER_STATE_LEN)?;
+        let (server_state, meta) = if legacy {
+            (server_state, None)
+        } else {
+            let meta_start = server_state
+                .len()
+                .checked_sub(STATE_META_LEN)
+                .ok_or(ProtocolError::SerializationError)?;
+            let (server_state, meta) = server_state.split_at(meta_start);
+            (server_state, Some(meta))
+        };
+        checks.check(meta)?;
+        let state = ServerLogin::<CS>::deserialize(server_state)?;
+        let login_finish_request = open_envelope::<CS>(
+            login_finish_request,
+            ObjectKind::CredentialFinalization,
+            &[LOGIN_FINISH_MESSAGE_LEN],
+        )?;
+        let login_finish_request = CredentialFinalization::<CS>::deserialize(login_finish_request)?;
         let result = state.finish(login_finish_request)?;
         Ok(ServerLoginFinishResult {
-            shared_secret: result.session_key.into_array(),
+            shared_secret: result.session_key.to_vec().into(),
         })
     }
 
-    pub struct ClientStateRegistration {
-        rng: ThreadRng,
-        state: Option<ClientRegistration<Cipher>>,
+    pub struct ClientStateRegistration<CS: Suite = Cipher> {
+        rng: StateRng,
+        state: Option<ClientRegistration<CS>>,
+        ksf: KsfParams,
     }
 
-    impl ClientStateRegistration {
+    impl<CS: Suite> ClientStateRegistration<CS> {
         pub fn setup() -> Self {
             Self {
-                rng: thread_rng(),
+                rng: StateRng::default(),
+                state: None,
+                ksf: KsfParams::default(),
+            }
+        }
+
+        /// Uses `rng` instead of the thread-local generator, e.g. a seeded one for reproducing
+        /// test vectors. Deserialized states use the thread-local generator.
+        #[cfg(any(test, feature = "test-vectors"))]
+        pub fn with_rng<R: CryptoRngCore + 'static>(rng: R) -> Self {
+            Self {
+                rng: StateRng::custom(rng),
                 state: None,
+                ksf: KsfParams::default(),
             }
         }
 
+        /// Also accepts envelopes and untagged states of the default suite, which get the default
+        /// KSF parameters.
         pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
-            let login = ClientRegistration::<Cipher>::deserialize(bytes)?;
+            let bytes = open_envelope::<CS>(
+                bytes,
+                ObjectKind::ClientRegistrationState,
+                &[REGISTER_CLIENT_STATE_LEN],
+            )?;
+            let (bytes, legacy) = untag::<CS>(bytes, REGISTER_CLIENT_STATE_LEN)?;
+            let (login, ksf) = KsfParams::split_trailing(bytes, legacy.then_some(bytes.len()))?;
+            let login = ClientRegistration::<CS>::deserialize(login)?;
 
             Ok(Self {
-                rng: thread_rng(),
+                rng: StateRng::default(),
                 state: Some(login),
+                ksf,
             })
         }
 
-        pub fn serialize(&self) -> [u8; REGISTER_CLIENT_STATE_LEN] {
-            self.state
-                .as_ref()
-                .expect("Can only serialize after first step is completed!")
-                .serialize()
-                .into_array()
+        /// Fails with `Error::InvalidState` before `client_register_start` is called.
+        pub fn serialize(&self) -> Result<SecretBytes, Error> {
+            let state = self.state.as_ref().ok_or(Error::InvalidState(
+                "can only serialize after `client_register_start`",
+            ))?;
+            let state = CS::serialize_client_registration(state);
+
+            Ok(tag::<CS>(&[state.as_slice(), &self.ksf.serialize()]).into())
+        }
+
+        pub fn serialize_envelope(&self) -> Result<SecretBytes, Error> {
+            Ok(encode_envelope(
+                ObjectKind::ClientRegistrationState,
+                CS::ID,
+                &self.serialize()?,
+            )
+            .into())
         }
     }
 
-    impl ClientStateLogin {
+    impl<CS: Suite> ClientStateLogin<CS> {
         pub fn setup() -> Self {
             Self {
-                rng: thread_rng(),
+                rng: StateRng::default(),
                 state: None,
             }
         }
 
+        /// See `ClientStateRegistration::with_rng`.
+        #[cfg(any(test, feature = "test-vectors"))]
+        pub fn with_rng<R: CryptoRngCore + 'static>(rng: R) -> Self {
+            Self {
+                rng: StateRng::custom(rng),
+                state: None,
+            }
+        }
+
+        /// Also accepts envelopes and untagged states of the default suite.
         pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
-            let login = ClientLogin::<Cipher>::deserialize(bytes)?;
+            let bytes = open_envelope::<CS>(
+                bytes,
+                ObjectKind::ClientLoginState,
+                &[LOGIN_CLIENT_STATE_LEN],
+            )?;
+            let (bytes, _) = untag::<CS>(bytes, LOGIN_CLIENT_STATE_LEN)?;
+            let login = ClientLogin::<CS>::deserialize(bytes)?;
 
             Ok(Self {
-                rng: thread_rng(),
+                rng: StateRng::default(),
                 state: Some(login),
             })
         }
 
-        pub fn serialize(&self) -> [u8; LOGIN_CLIENT_STATE_LEN] {
-            self.state
-                .as_ref()
-                .expect("Can only serialize after first step is completed!")
-                .serialize()
-                .into_array()
+        /// Fails with `Error::InvalidState` before `client_login_start` is called.
+        pub fn serialize(&self) -> Result<SecretBytes, Error> {
+            let state = self.state.as_ref().ok_or(Error::InvalidState(
+                "can only serialize after `client_login_start`",
+            ))?;
+            let state = CS::serialize_client_login(state);
+
+            Ok(tag::<CS>(&[state.as_slice()]).into())
+        }
+
+        pub fn serialize_envelope(&self) -> Result<SecretBytes, Error> {
+            Ok(encode_envelope(ObjectKind::ClientLoginState, CS::ID, &self.serialize()?).into())
         }
     }
 
-    pub fn client_register_start(
-        client_state: &mut ClientStateRegistration,
+    pub fn client_register_start<CS: Suite>(
+        client_state: &mut ClientStateRegistration<CS>,
         password: &[u8],
+        ksf_params: KsfParams,
     ) -> Result<ClientRegistrationStartResult, Error> {
-        let result = ClientRegistration::start(&mut client_state.rng, password)?;
+        ksf_params.to_ksf()?;
+        let result = ClientRegistration::<CS>::start(&mut client_state.rng, password)?;
 
         client_state.state = Some(result.state);
+        client_state.ksf = ksf_params;
 
         Ok(ClientRegistrationStartResult {
-            response: result.message.serialize().into_array(),
+            response: result.message.serialize().to_vec(),
         })
     }
 
-    pub fn server_register_start(
-        server_setup: &mut ServerSetupView,
+    pub fn server_register_start<CS: Suite>(
+        server_setup: &mut ServerSetupView<CS>,
         register_start_request: &[u8],
         user_id: &[u8],
     ) -> Result<ServerRegistrationStartResult, Error> {
-        let register_start_request =
-            RegistrationRequest::<Cipher>::deserialize(register_start_request)?;
-
-        let result = ServerRegistration::<Cipher>::start(
-            server_setup.setup,
+        let register_start_request = open_envelope::<CS>(
             register_start_request,
-            user_id,
+            ObjectKind::RegistrationRequest,
+            &[REGISTER_CLIENT_MESSAGE_LEN],
         )?;
+        let register_start_request =
+            RegistrationRequest::<CS>::deserialize(register_start_request)?;
+
+        let result =
+            ServerRegistration::<CS>::start(server_setup.setup, register_start_request, user_id)?;
 
         Ok(ServerRegistrationStartResult {
-            response: result.message.serialize().into_array(),
+            response: CS::serialize_registration_response(&result.message),
         })
     }
 
-    pub fn client_register_finish(
-        client_state: &mut ClientStateRegistration,
+    /// The `identifiers` are bound into the envelope stored in the password file, so the same
+    /// identifiers must be used at every login.
+    pub fn client_register_finish<CS: Suite>(
+        client_state: &mut ClientStateRegistration<CS>,
         password: &[u8],
         server_message: &[u8],
+        identifiers: Identifiers,
     ) -> Result<ClientRegistrationFinishResult, Error> {
-        if client_state.state.is_none() {
-            panic!("Client state not initialized! Run `server_register_start` first!")
-        }
-        let state = client_state.state.take().unwrap();
-        let server_message = RegistrationResponse::deserialize(server_message)?;
+        let ksf = client_state.ksf.to_ksf()?;
+        let state = client_state.state.take().ok_or(Error::InvalidState(
+            "client state not initialized, run `client_register_start` first",
+        ))?;
+        let server_message = open_envelope::<CS>(
+            server_message,
+            ObjectKind::RegistrationResponse,
+            &[REGISTER_SERVER_MESSAGE_LEN],
+        )?;
+        let server_message = RegistrationResponse::<CS>::deserialize(server_message)?;
 
         let result = state.finish(
             &mut client_state.rng,
             password,
             server_message,
-            ClientRegistrationFinishParameters::default(),
+            ClientRegistrationFinishParameters::new(identifiers, Some(&ksf)),
         )?;
 
+        // The KSF parameters are sent along so the server can record them in the password file
+        let mut response = CS::serialize_registration_upload(&result.message);
+        response.extend_from_slice(&client_state.ksf.serialize());
+
         Ok(ClientRegistrationFinishResult {
-            response: result.message.serialize().into_array(),
+            response,
+            export_key: result.export_key.to_vec().into(),
+            server_public_key: result.server_s_pk.serialize().to_vec(),
         })
     }
 
-    pub fn server_register_finish(register_finish_request: &[u8]) -> Result<PasswordFile, Error> {
+    /// Also accepts messages from clients of the default suite that do not send their KSF
+    /// parameters, in which case the defaults are recorded.
+    pub fn server_register_finish<CS: Suite>(
+        register_finish_request: &[u8],
+    ) -> Result<PasswordFile<CS>, Error> {
+        let register_finish_request = open_envelope::<CS>(
+            register_finish_request,
+            ObjectKind::RegistrationUpload,
+            &[REGISTER_UPLOAD_LEN, REGISTER_FINISH_MESSAGE_LEN],
+        )?;
+        let legacy_len = (CS::ID == SuiteId::Ristretto255Argon2).then_some(REGISTER_UPLOAD_LEN);
+        let (register_finish_request, ksf) =
+            KsfParams::split_trailing(register_finish_request, legacy_len)?;
         let register_finish_request =
-            RegistrationUpload::<Cipher>::deserialize(register_finish_request)?;
+            RegistrationUpload::<CS>::deserialize(register_finish_request)?;
 
         let result = ServerRegistration::finish(register_finish_request);
 
-        Ok(PasswordFile(result))
+        Ok(PasswordFile {
+            registration: result,
+            ksf,
+            key_id: None,
+        })
+    }
+
+    /// Label under which the new registration upload of a password change is authenticated with
+    /// the session key.
+    const CHANGE_PASSWORD_MAC_LABEL: &[u8] = b"opaque-borink change password";
+
+    /// Client state of a password change, which logs in with the old password and registers the
+    /// new one at the same time.
+    pub struct ClientStateChangePassword<CS: Suite = Cipher> {
+        login: ClientStateLogin<CS>,
+        registration: ClientStateRegistration<CS>,
+    }
+
+    impl<CS: Suite> ClientStateChangePassword<CS> {
+        pub fn setup() -> Self {
+            Self {
+                login: ClientStateLogin::setup(),
+                registration: ClientStateRegistration::setup(),
+            }
+        }
+
+        /// See `ClientStateRegistration::with_rng`. The login and the registration share `rng`.
+        #[cfg(any(test, feature = "test-vectors"))]
+        pub fn with_rng<R: CryptoRngCore + 'static>(rng: R) -> Self {
+            let rng = StateRng::custom(rng);
+
+            Self {
+                login: ClientStateLogin {
+                    rng: rng.clone(),
+                    state: None,
+                },
+                registration: ClientStateRegistration {
+                    rng,
+                    state: None,
+                    ksf: KsfParams::default(),
+                },
+            }
+        }
+
+        /// Also accepts envelopes.
+        pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+            let bytes = open_envelope::<CS>(bytes, ObjectKind::ClientChangePasswordState, &[])?;
+            // There is no untagged legacy form
+            let (bytes, _) = untag::<CS>(bytes, usize::MAX)?;
+            let [login, registration] = split_parts(bytes)?;
+
+            Ok(Self {
+                login: ClientStateLogin::deserialize(login)?,
+                registration: ClientStateRegistration::deserialize(registration)?,
+            })
+        }
+
+        /// Fails with `Error::InvalidState` before `client_change_password_start` is called.
+        pub fn serialize(&self) -> Result<SecretBytes, Error> {
+            if self.login.state.is_none() || self.registration.state.is_none() {
+                return Err(Error::InvalidState(
+                    "can only serialize after `client_change_password_start`",
+                ));
+            }
+            let login = self.login.serialize()?;
+            let registration = self.registration.serialize()?;
+            let parts = SecretBytes::from(join_parts(&[&login, &registration]));
+
+            Ok(tag::<CS>(&[&parts]).into())
+        }
+
+        pub fn serialize_envelope(&self) -> Result<SecretBytes, Error> {
+            Ok(encode_envelope(
+                ObjectKind::ClientChangePasswordState,
+                CS::ID,
+                &self.serialize()?,
+            )
+            .into())
+        }
     }
 
+    /// Starts changing the password of a registered user. The `ksf_params` are used for the new
+    /// password.
+    pub fn client_change_password_start<CS: Suite>(
+        client_state: &mut ClientStateChangePassword<CS>,
+        old_password: &[u8],
+        new_password: &[u8],
+        ksf_params: KsfParams,
+    ) -> Result<ClientChangePasswordStartResult, Error> {
+        let registration =
+            client_register_start(&mut client_state.registration, new_password, ksf_params)?;
+        let login = client_login_start(&mut client_state.login, old_password)?;
+
+        Ok(ClientChangePasswordStartResult {
+            response: join_parts(&[&login.response, &registration.response]),
+        })
+    }
+
+    /// Starts both a login using the current `password_file` and a registration of the new
+    /// password. The `identifiers` and `context` are used as in `server_login_start`, the
+    /// `identifiers` also apply to the new password file. Only the returned state has to be kept
+    /// until `server_change_password_finish`.
+    pub fn server_change_password_start<CS: Suite>(
+        setup: &mut ServerSetupView<CS>,
+        password_file: &PasswordFile<CS>,
+        change_password_request: &[u8],
+        user_id: &str,
+        identifiers: Identifiers,
+        context: Option<&[u8]>,
+    ) -> Result<ServerLoginStartResult, Error> {
+        let change_password_request = open_envelope::<CS>(
+            change_password_request,
+            ObjectKind::ChangePasswordRequest,
+            &[],
+        )?;
+        let [login_request, register_request] = split_parts(change_password_request)?;
+
+        let login = server_login_start(
+            setup,
+            Some(password_file),
+            login_request,
+            user_id,
+            identifiers,
+            context,
+        )?;
+        let registration = server_register_start(setup, register_request, user_id.as_bytes())?;
+
+        Ok(ServerLoginStartResult {
+            response: join_parts(&[&login.response, &registration.response]),
+            state: login.state,
+        })
+    }
+
+    /// Logs in with the old password and finishes the registration of the new one. The
+    /// `ksf_params` are those of the current password file. The registration upload is
+    /// authenticated with the session key, so the server only accepts it from a client that
+    /// knows the old password. The `expected_server_public_key` is checked as in
+    /// `client_login_finish`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn client_change_password_finish<CS: Suite>(
+        client_state: &mut ClientStateChangePassword<CS>,
+        old_password: &[u8],
+        new_password: &[u8],
+        server_message: &[u8],
+        ksf_params: KsfParams,
+        identifiers: Identifiers,
+        context: Option<&[u8]>,
+        expected_server_public_key: Option<&[u8]>,
+    ) -> Result<ClientChangePasswordFinishResult, Error> {
+        let server_message =
+            open_envelope::<CS>(server_message, ObjectKind::ChangePasswordResponse, &[])?;
+        let [login_response, register_response] = split_parts(server_message)?;
+
+        let login = client_login_finish(
+            &mut client_state.login,
+            old_password,
+            login_response,
+            ksf_params,
+            identifiers,
+            context,
+            expected_server_public_key,
+        )?;
+        let registration = client_register_finish(
+            &mut client_state.registration,
+            new_password,
+            register_response,
+            identifiers,
+        )?;
+        let mac = change_password_mac(&login.shared_secret, &registration.response).finalize();
+
+        Ok(ClientChangePasswordFinishResult {
+            response: join_parts(&[&login.response, &registration.response, &mac.into_bytes()]),
+            shared_secret: login.shared_secret,
+            export_key: registration.export_key,
+            old_export_key: login.export_key,
+        })
+    }
+
+    /// Finishes the login and returns the new password file, which replaces the current one. It
+    /// is registered with the setup passed to `server_change_password_start`, so when using a
+    /// `ServerSetupRing` the key id of the current password file should be recorded in it.
+    pub fn server_change_password_finish<CS: Suite>(
+        change_password_finish_request: &[u8],
+        server_state: &[u8],
+        checks: &StateChecks,
+    ) -> Result<ServerChangePasswordFinishResult<CS>, Error> {
+        let change_password_finish_request = open_envelope::<CS>(
+            change_password_finish_request,
+            ObjectKind::ChangePasswordFinalization,
+            &[],
+        )?;
+        let [login_finish_request, register_finish_request, mac] =
+            split_parts(change_password_finish_request)?;
+
+        let login = server_login_finish::<CS>(login_finish_request, server_state, checks)?;
+        change_password_mac(&login.shared_secret, register_finish_request)
+            .verify_slice(mac)
+            .map_err(|_| ProtocolError::InvalidLoginError)?;
+        let password_file = server_register_finish::<CS>(register_finish_request)?;
+
+        Ok(ServerChangePasswordFinishResult {
+            password_file,
+            shared_secret: login.shared_secret,
+        })
+    }
+
+    fn change_password_mac(session_key: &[u8], register_finish_request: &[u8]) -> Hmac<Sha512> {
+        let mut mac =
+            Hmac::<Sha512>::new_from_slice(session_key).expect("HMAC takes keys of any length");
+        mac.update(CHANGE_PASSWORD_MAC_LABEL);
+        mac.update(register_finish_request);
+
+        mac
+    }
+
+    /// Concatenates messages, each prefixed by its length as a big-endian u16.
+    fn join_parts(parts: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(parts.iter().map(|part| 2 + part.len()).sum());
+        for part in parts {
+            bytes.extend_from_slice(&(part.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(part);
+        }
+
+        bytes
+    }
+
+    /// Splits the output of `join_parts` into exactly `N` messages.
+    fn split_parts<const N: usize>(mut bytes: &[u8]) -> Result<[&[u8]; N], Error> {
+        let mut parts = [&[][..]; N];
+        for part in parts.iter_mut() {
+            if bytes.len() < 2 {
+                return Err(ProtocolError::SerializationError.into());
+            }
+            let (len, rest) = bytes.split_at(2);
+            let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+            if rest.len() < len {
+                return Err(ProtocolError::SerializationError.into());
+            }
+            (*part, bytes) = rest.split_at(len);
+        }
+        if !bytes.is_empty() {
+            return Err(ProtocolError::SerializationError.into());
+        }
+
+        Ok(parts)
+    }
+
+    // All lengths are those of the default suite. The serialized setups, password files and states
+    // are prefixed with a suite tag, the other lengths refer to the untagged opaque-ke types.
+
     pub const SERVER_SETUP_LEN: usize = 128;
+    pub const SERVER_SETUP_SERIALIZED_LEN: usize = SUITE_TAG_LEN + SERVER_SETUP_LEN;
 
     pub const LOGIN_SERVER_MESSAGE_LEN: usize = 320;
     pub const LOGIN_SERVER_STATE_LEN: usize = 192;
+    pub const LOGIN_SERVER_STATE_SERIALIZED_LEN: usize =
+        SUITE_TAG_LEN + LOGIN_SERVER_STATE_LEN + STATE_META_LEN;
     pub const LOGIN_CLIENT_STATE_LEN: usize = 192;
+    pub const LOGIN_CLIENT_STATE_SERIALIZED_LEN: usize = SUITE_TAG_LEN + LOGIN_CLIENT_STATE_LEN;
     pub const LOGIN_CLIENT_MESSAGE_LEN: usize = 96;
     pub const LOGIN_FINISH_MESSAGE_LEN: usize = 64;
 
     pub const REGISTER_SERVER_MESSAGE_LEN: usize = 64;
     pub const REGISTER_CLIENT_STATE_LEN: usize = 64;
+    pub const REGISTER_CLIENT_STATE_SERIALIZED_LEN: usize =
+        SUITE_TAG_LEN + REGISTER_CLIENT_STATE_LEN + KSF_PARAMS_LEN;
     pub const REGISTER_CLIENT_MESSAGE_LEN: usize = 32;
-    pub const REGISTER_FINISH_MESSAGE_LEN: usize = 192;
+    /// Length of the opaque-ke `RegistrationUpload`, without the KSF parameters.
+    pub const REGISTER_UPLOAD_LEN: usize = 192;
+    pub const REGISTER_FINISH_MESSAGE_LEN: usize = REGISTER_UPLOAD_LEN + KSF_PARAMS_LEN;
 
     pub const SHARED_SECRET_LEN: usize = 64;
+    pub const EXPORT_KEY_LEN: usize = 64;
+    pub const SERVER_PUBLIC_KEY_LEN: usize = 32;
+
+    pub const PASSWORD_FILE_LEN: usize = 352;
+    /// Length of the opaque-ke `ServerRegistration`, without the KSF parameters.
+    pub const PASSWORD_FILE_REGISTRATION_LEN: usize = 192;
+    /// Length of a serialized password file without a key id.
+    pub const PASSWORD_FILE_SERIALIZED_LEN: usize =
+        SUITE_TAG_LEN + PASSWORD_FILE_REGISTRATION_LEN + KSF_PARAMS_LEN;
+
+    // The messages and states in the results are bytes, or the wrappers from `typed` when
+    // returned by the functions in that module.
+
+    pub struct ServerLoginStartResult<M = Vec<u8>, S = SecretBytes> {
+        pub response: M,
+        pub state: S,
+    }
 
-    pub const PASSWORD_FILE_LEN: usize = 328;
-    pub const PASSWORD_FILE_SERIALIZED_LEN: usize = 192;
+    pub struct ClientLoginStartResult<M = Vec<u8>> {
+        pub response: M,
+    }
 
-    pub struct ServerLoginStartResult {
-        pub response: [u8; LOGIN_SERVER_MESSAGE_LEN],
-        pub state: [u8; LOGIN_SERVER_STATE_LEN],
+    pub struct ClientLoginFinishResult<M = Vec<u8>> {
+        pub response: M,
+        pub shared_secret: SecretBytes,
+        /// Key that only the client can derive from the password, equal to the one of the
+        /// registration. It can be used to encrypt data client-side.
+        pub export_key: SecretBytes,
+        /// Static public key of the server, which can be pinned using the
+        /// `expected_server_public_key` of `client_login_finish`.
+        pub server_public_key: Vec<u8>,
     }
 
-    pub struct ClientLoginStartResult {
-        pub response: [u8; LOGIN_CLIENT_MESSAGE_LEN],
+    pub struct ServerLoginFinishResult {
+        pub shared_secret: SecretBytes,
     }
 
-    pub struct ClientLoginFinishResult {
-        pub response: [u8; LOGIN_FINISH_MESSAGE_LEN],
-        pub shared_secret: [u8; SHARED_SECRET_LEN],
+    pub struct ClientRegistrationStartResult<M = Vec<u8>> {
+        pub response: M,
     }
 
-    pub struct ServerLoginFinishResult {
-        pub shared_secret: [u8; SHARED_SECRET_LEN],
+    pub struct ClientRegistrationFinishResult<M = Vec<u8>> {
+        pub response: M,
+        /// Equal to the `export_key` of every login with this registration.
+        pub export_key: SecretBytes,
+        /// Static public key of the server the client registered with.
+        pub server_public_key: Vec<u8>,
+    }
+
+    pub struct ServerRegistrationStartResult<M = Vec<u8>> {
+        pub response: M,
     }
 
-    pub struct ClientRegistrationStartResult {
-        pub response: [u8; REGISTER_CLIENT_MESSAGE_LEN],
+    pub struct ClientChangePasswordStartResult<M = Vec<u8>> {
+        pub response: M,
     }
 
-    pub struct ClientRegistrationFinishResult {
-        pub response: [u8; REGISTER_FINISH_MESSAGE_LEN],
+    pub struct ClientChangePasswordFinishResult<M = Vec<u8>> {
+        pub response: M,
+        pub shared_secret: SecretBytes,
+        /// Export key of the new password.
+        pub export_key: SecretBytes,
+        /// Export key of the old password, e.g. for decrypting data to encrypt again under the
+        /// new `export_key`.
+        pub old_export_key: SecretBytes,
     }
 
-    pub struct ServerRegistrationStartResult {
-        pub response: [u8; REGISTER_SERVER_MESSAGE_LEN],
+    pub struct ServerChangePasswordFinishResult<CS: Suite = Cipher> {
+        pub password_file: PasswordFile<CS>,
+        pub shared_secret: SecretBytes,
     }
 
     #[cfg(test)]
     mod test {
+        use opaque_ke::errors::ProtocolError;
         use opaque_ke::{ClientLogin, ClientRegistration};
 
+        use crate::suite::{P256Argon2, P384Argon2, Ristretto255Argon2};
+        use crate::ErrorKind;
+
         use super::*;
 
         #[test]
@@ -365,26 +1131,12 @@ pub(crate) mod opaque_impl {
             let a = ClientRegistration::start(&mut rng, "my_pass".as_bytes()).unwrap();
 
             assert_eq!(REGISTER_CLIENT_MESSAGE_LEN, a.message.serialize().len());
-            assert_eq!(
-                a.message.serialize().as_slice(),
-                a.message
-                    .serialize()
-                    .into_array::<REGISTER_CLIENT_MESSAGE_LEN>()
-                    .as_slice()
-            );
             assert_eq!(REGISTER_CLIENT_STATE_LEN, a.state.serialize().len());
 
             let b =
                 ServerRegistration::start(&server_setup, a.message, "my_user".as_bytes()).unwrap();
 
             assert_eq!(REGISTER_SERVER_MESSAGE_LEN, b.message.serialize().len());
-            assert_eq!(
-                b.message.serialize().as_slice(),
-                b.message
-                    .serialize()
-                    .into_array::<REGISTER_SERVER_MESSAGE_LEN>()
-                    .as_slice()
-            );
 
             let c = a
                 .state
@@ -396,19 +1148,19 @@ pub(crate) mod opaque_impl {
                 )
                 .unwrap();
 
-            assert_eq!(REGISTER_FINISH_MESSAGE_LEN, c.message.serialize().len());
-            assert_eq!(
-                c.message.serialize().as_slice(),
-                c.message
-                    .serialize()
-                    .into_array::<REGISTER_FINISH_MESSAGE_LEN>()
-                    .as_slice()
-            );
+            assert_eq!(REGISTER_UPLOAD_LEN, c.message.serialize().len());
+            assert_eq!(EXPORT_KEY_LEN, c.export_key.len());
+            assert_eq!(SERVER_PUBLIC_KEY_LEN, c.server_s_pk.serialize().len());
 
             let pw_file = ServerRegistration::finish(c.message);
 
+            assert_eq!(PASSWORD_FILE_REGISTRATION_LEN, pw_file.serialize().len());
             assert_eq!(
-                size_of_val(&PasswordFile(pw_file.clone())),
+                size_of_val(&PasswordFile {
+                    registration: pw_file.clone(),
+                    ksf: KsfParams::default(),
+                    key_id: None
+                }),
                 PASSWORD_FILE_LEN
             );
             assert_eq!(size_of::<PasswordFile>(), PASSWORD_FILE_LEN);
@@ -416,13 +1168,6 @@ pub(crate) mod opaque_impl {
             let d = ClientLogin::<Cipher>::start(&mut rng, "my_pass".as_bytes()).unwrap();
 
             assert_eq!(LOGIN_CLIENT_MESSAGE_LEN, d.message.serialize().len());
-            assert_eq!(
-                d.message.serialize().as_slice(),
-                d.message
-                    .serialize()
-                    .into_array::<LOGIN_CLIENT_MESSAGE_LEN>()
-                    .as_slice()
-            );
             assert_eq!(LOGIN_CLIENT_STATE_LEN, d.state.serialize().len());
 
             let e = ServerLogin::<Cipher>::start(
@@ -435,12 +1180,6 @@ pub(crate) mod opaque_impl {
             )
             .unwrap();
 
-            let f: [u8; LOGIN_SERVER_MESSAGE_LEN] = e.message.serialize().into_array();
-            let g: [u8; LOGIN_SERVER_MESSAGE_LEN] =
-                unsafe { core::mem::transmute(e.message.serialize()) };
-
-            assert_eq!(&f, e.message.serialize().as_slice());
-            assert_eq!(&g, &f);
             assert_eq!(LOGIN_SERVER_MESSAGE_LEN, e.message.serialize().len());
             assert_eq!(LOGIN_SERVER_STATE_LEN, e.state.serialize().len());
 
@@ -454,86 +1193,628 @@ pub(crate) mod opaque_impl {
                 .unwrap();
 
             assert_eq!(LOGIN_FINISH_MESSAGE_LEN, h.message.serialize().len());
+            assert_eq!(SHARED_SECRET_LEN, h.session_key.len());
+        }
+
+        /// Parameters of the different steps in `register_and_login`, which are expected to
+        /// match for the login to succeed.
+        #[derive(Default)]
+        struct Options<'a> {
+            ksf: KsfParams,
+            login_ksf: KsfParams,
+            register_identifiers: Identifiers<'a>,
+            client_identifiers: Identifiers<'a>,
+            server_identifiers: Identifiers<'a>,
+            client_context: Option<&'a [u8]>,
+            server_context: Option<&'a [u8]>,
+            expected_server_public_key: Option<&'a [u8]>,
+        }
+
+        fn register_and_login<CS: Suite>(
+            options: Options,
+        ) -> Result<ClientLoginFinishResult, Error> {
+            let ksf = options.ksf;
+            let setup = ServerSetup::<CS>::create();
+            let setup = ServerSetup::<CS>::deserialize(&setup.serialize()).unwrap();
+            let password = "my_pass".as_bytes();
+
+            let mut client_state = ClientStateRegistration::<CS>::setup();
+            let client_start = client_register_start(&mut client_state, password, ksf).unwrap();
+            let client_state = client_state.serialize().unwrap();
+            let mut client_state =
+                ClientStateRegistration::<CS>::deserialize(&client_state).unwrap();
+            let server_start =
+                server_register_start(&mut setup.view(), &client_start.response, b"my_user")
+                    .unwrap();
+            let client_finish = client_register_finish(
+                &mut client_state,
+                password,
+                &server_start.response,
+                options.register_identifiers,
+            )
+            .unwrap();
+            let export_key = client_finish.export_key;
+            assert_eq!(setup.public_key(), client_finish.server_public_key);
+            let password_file = server_register_finish::<CS>(&client_finish.response).unwrap();
+            let password_file =
+                PasswordFile::<CS>::deserialize(&password_file.serialize()).unwrap();
+            assert_eq!(ksf, password_file.ksf_params());
+
+            let mut client_state = ClientStateLogin::<CS>::setup();
+            let client_start = client_login_start(&mut client_state, password).unwrap();
+            let client_state = client_state.serialize().unwrap();
+            let mut client_state = ClientStateLogin::<CS>::deserialize(&client_state).unwrap();
+            let server_start = server_login_start(
+                &mut setup.view(),
+                Some(&password_file),
+                &client_start.response,
+                "my_user",
+                options.server_identifiers,
+                options.server_context,
+            )
+            .unwrap();
+            let client_finish = client_login_finish(
+                &mut client_state,
+                password,
+                &server_start.response,
+                options.login_ksf,
+                options.client_identifiers,
+                options.client_context,
+                options.expected_server_public_key,
+            )?;
+            let server_finish = server_login_finish::<CS>(
+                &client_finish.response,
+                &server_start.state,
+                &StateChecks::default(),
+            )
+            .unwrap();
+            assert_eq!(*client_finish.shared_secret, *server_finish.shared_secret);
+            assert_eq!(*export_key, *client_finish.export_key);
+            assert_eq!(setup.public_key(), client_finish.server_public_key);
+
+            Ok(client_finish)
+        }
+
+        #[test]
+        fn custom_ksf_params() {
+            let ksf = KsfParams::new(argon2::Algorithm::Argon2i, 1024, 1, 1).unwrap();
+
+            let options = || Options {
+                ksf,
+                login_ksf: ksf,
+                ..Default::default()
+            };
+
+            assert!(register_and_login::<Cipher>(options()).is_ok());
+            assert!(matches!(
+                register_and_login::<Cipher>(Options {
+                    login_ksf: KsfParams::default(),
+                    ..options()
+                }),
+                Err(Error::ProtocolError(ProtocolError::InvalidLoginError))
+            ));
+        }
+
+        #[test]
+        fn all_suites() {
+            let ksf = KsfParams::new(argon2::Algorithm::Argon2id, 1024, 1, 1).unwrap();
+
+            let options = || Options {
+                ksf,
+                login_ksf: ksf,
+                ..Default::default()
+            };
+
+            let result = register_and_login::<Ristretto255Argon2>(options()).unwrap();
+            assert_eq!(64, result.shared_secret.len());
+            assert_eq!(64, result.export_key.len());
+            let result = register_and_login::<P256Argon2>(options()).unwrap();
+            assert_eq!(32, result.shared_secret.len());
+            assert_eq!(32, result.export_key.len());
+            let result = register_and_login::<P384Argon2>(options()).unwrap();
+            assert_eq!(48, result.shared_secret.len());
+            assert_eq!(48, result.export_key.len());
+        }
+
+        #[test]
+        fn identifiers() {
+            let ksf = KsfParams::new(argon2::Algorithm::Argon2id, 1024, 1, 1).unwrap();
+            let identifiers = Identifiers {
+                client: Some(b"my_user"),
+                server: Some(b"example.com"),
+            };
+            let options = || Options {
+                ksf,
+                login_ksf: ksf,
+                register_identifiers: identifiers,
+                client_identifiers: identifiers,
+                server_identifiers: identifiers,
+                ..Default::default()
+            };
+
+            assert!(register_and_login::<Cipher>(options()).is_ok());
+            // The server uses a different hostname
+            assert!(matches!(
+                register_and_login::<Cipher>(Options {
+                    server_identifiers: Identifiers {
+                        server: Some(b"example.org"),
+                        ..identifiers
+                    },
+                    ..options()
+                }),
+                Err(Error::ProtocolError(ProtocolError::InvalidLoginError))
+            ));
+            // The client logs in with identifiers that differ from the registration
+            assert!(matches!(
+                register_and_login::<Cipher>(Options {
+                    client_identifiers: Identifiers::default(),
+                    server_identifiers: Identifiers::default(),
+                    ..options()
+                }),
+                Err(Error::ProtocolError(ProtocolError::InvalidLoginError))
+            ));
+        }
+
+        #[test]
+        fn context() {
+            let ksf = KsfParams::new(argon2::Algorithm::Argon2id, 1024, 1, 1).unwrap();
+            let options = |client_context: Option<&'static [u8]>,
+                           server_context: Option<&'static [u8]>| {
+                Options {
+                    ksf,
+                    login_ksf: ksf,
+                    client_context,
+                    server_context,
+                    ..Default::default()
+                }
+            };
+
+            assert!(register_and_login::<Cipher>(options(
+                Some(b"myapp-v2-login"),
+                Some(b"myapp-v2-login")
+            ))
+            .is_ok());
+            assert!(matches!(
+                register_and_login::<Cipher>(options(
+                    Some(b"myapp-v2-login"),
+                    Some(b"otherapp-login")
+                )),
+                Err(Error::ProtocolError(ProtocolError::InvalidLoginError))
+            ));
+            assert!(matches!(
+                register_and_login::<Cipher>(options(None, Some(b"myapp-v2-login"))),
+                Err(Error::ProtocolError(ProtocolError::InvalidLoginError))
+            ));
+        }
+
+        #[test]
+        fn server_public_key() {
+            let other = ServerSetup::<Cipher>::create().public_key();
+            assert_eq!(SERVER_PUBLIC_KEY_LEN, other.len());
+
+            let err = register_and_login::<Cipher>(Options {
+                expected_server_public_key: Some(&other),
+                ..Default::default()
+            })
+            .err()
+            .unwrap();
+            assert!(matches!(err, Error::ServerPublicKeyMismatch));
+            assert_eq!(ErrorKind::InvalidLogin, err.kind());
+        }
+
+        #[test]
+        fn unknown_user() {
+            let setup = ServerSetup::<Cipher>::create();
+            let password = b"my_pass";
+
+            let mut client_state = ClientStateLogin::<Cipher>::setup();
+            let client_start = client_login_start(&mut client_state, password).unwrap();
+            let server_start = server_login_start(
+                &mut setup.view(),
+                None,
+                &client_start.response,
+                "unknown_user",
+                Identifiers::default(),
+                None,
+            )
+            .unwrap();
+            assert_eq!(LOGIN_SERVER_MESSAGE_LEN, server_start.response.len());
+            assert_eq!(LOGIN_SERVER_STATE_SERIALIZED_LEN, server_start.state.len());
+
+            assert!(matches!(
+                client_login_finish(
+                    &mut client_state,
+                    password,
+                    &server_start.response,
+                    KsfParams::default(),
+                    Identifiers::default(),
+                    None,
+                    None,
+                ),
+                Err(Error::ProtocolError(ProtocolError::InvalidLoginError))
+            ));
+            // A client that knows nothing but the response also fails to finish on the server
+            assert!(matches!(
+                server_login_finish::<Cipher>(
+                    &[0u8; LOGIN_FINISH_MESSAGE_LEN],
+                    &server_start.state,
+                    &StateChecks::default()
+                ),
+                Err(Error::ProtocolError(_))
+            ));
+        }
+
+        #[test]
+        fn out_of_order() {
+            let setup = ServerSetup::<Cipher>::create();
+            let password = b"my_pass";
+
+            let mut client_state = ClientStateLogin::<Cipher>::setup();
+            assert!(matches!(
+                client_state.serialize(),
+                Err(Error::InvalidState(_))
+            ));
+            assert!(matches!(
+                client_login_finish(
+                    &mut client_state,
+                    password,
+                    &[0u8; LOGIN_SERVER_MESSAGE_LEN],
+                    KsfParams::default(),
+                    Identifiers::default(),
+                    None,
+                    None,
+                ),
+                Err(Error::InvalidState(_))
+            ));
+
+            let mut client_state = ClientStateRegistration::<Cipher>::setup();
+            assert!(matches!(
+                client_state.serialize_envelope(),
+                Err(Error::InvalidState(_))
+            ));
+            assert!(matches!(
+                client_register_finish(
+                    &mut client_state,
+                    password,
+                    &[0u8; REGISTER_SERVER_MESSAGE_LEN],
+                    Identifiers::default(),
+                ),
+                Err(Error::InvalidState(_))
+            ));
+
+            // A state can only be finished once
+            let client_start =
+                client_register_start(&mut client_state, password, KsfParams::default()).unwrap();
+            let server_start =
+                server_register_start(&mut setup.view(), &client_start.response, b"my_user")
+                    .unwrap();
+            assert!(client_register_finish(
+                &mut client_state,
+                password,
+                &server_start.response,
+                Identifiers::default(),
+            )
+            .is_ok());
+            assert!(matches!(
+                client_register_finish(
+                    &mut client_state,
+                    password,
+                    &server_start.response,
+                    Identifiers::default(),
+                ),
+                Err(Error::InvalidState(_))
+            ));
+        }
+
+        #[test]
+        fn error_kinds() {
+            use std::error::Error as _;
+
+            let err = Error::from(ProtocolError::InvalidLoginError);
+            assert_eq!(ErrorKind::InvalidLogin, err.kind());
+            assert_eq!("invalid_login", err.code());
+            assert!(err.source().is_some());
+
+            let err = ServerSetup::<Cipher>::deserialize(&[1u8; 3]).err().unwrap();
+            assert_eq!(ErrorKind::MalformedMessage, err.kind());
+
+            let err = crate::encoded::decode_string("not base64!").err().unwrap();
+            assert_eq!(ErrorKind::InvalidBase64, err.kind());
+            assert!(err.source().is_some());
+
+            let setup = ServerSetup::<Cipher>::create().serialize_envelope();
+            let err = PasswordFile::<Cipher>::deserialize(&setup).err().unwrap();
+            assert_eq!(ErrorKind::WrongObjectKind, err.kind());
             assert_eq!(
-                h.message.serialize().as_slice(),
-                h.message
-                    .serialize()
-                    .into_array::<LOGIN_FINISH_MESSAGE_LEN>()
+                "expected a PasswordFile, found a ServerSetup",
+                err.to_string()
             );
-            assert_eq!(64, h.session_key.len());
-        }
-    }
-}
 
-#[cfg(test)]
-pub mod test_util {
-    use super::opaque_impl::*;
+            let err = ClientStateLogin::<Cipher>::setup()
+                .serialize()
+                .err()
+                .unwrap();
+            assert_eq!(ErrorKind::InvalidState, err.kind());
+            assert!(err.source().is_none());
 
-    pub fn gen_password_file_with_setup_and_pw(setup: &mut ServerSetupView, user_id: &[u8], password: &[u8]) -> PasswordFile {
-        let mut client_state = ClientStateRegistration::setup();
+            let err = "p521_argon2".parse::<SuiteId>().err().unwrap();
+            assert_eq!(ErrorKind::InvalidParameters, err.kind());
+        }
 
-        let client_start = client_register_start(&mut client_state, password).unwrap();
-        
-        let server_start = server_register_start(setup, &client_start.response, user_id).unwrap();
+        #[test]
+        fn suite_mismatch() {
+            let setup = ServerSetup::<P256Argon2>::create().serialize();
 
-        let client_finish = client_register_finish(&mut client_state, password, &server_start.response).unwrap();
+            assert_eq!(
+                SuiteId::P256Argon2,
+                SuiteId::from_serialized(&setup, SERVER_SETUP_LEN).unwrap()
+            );
+            assert!(matches!(
+                ServerSetup::<Cipher>::deserialize(&setup),
+                Err(Error::SuiteMismatch {
+                    expected: SuiteId::Ristretto255Argon2,
+                    found: SuiteId::P256Argon2
+                })
+            ));
+        }
 
-        let server_finish = server_register_finish(&client_finish.response).unwrap();
+        #[test]
+        fn envelopes() {
+            let setup = ServerSetup::<P384Argon2>::create();
+            let envelope = setup.serialize_envelope();
 
-        server_finish
-    }
-}
+            assert_eq!(
+                SuiteId::P384Argon2,
+                SuiteId::from_serialized(&envelope, SERVER_SETUP_LEN).unwrap()
+            );
+            let setup = ServerSetup::<P384Argon2>::deserialize(&envelope).unwrap();
+            assert!(matches!(
+                PasswordFile::<P384Argon2>::deserialize(&envelope),
+                Err(Error::WrongObjectKind {
+                    expected: ObjectKind::PasswordFile,
+                    found: ObjectKind::ServerSetup
+                })
+            ));
+
+            let password = b"my_pass";
+            let mut client_state = ClientStateRegistration::<P384Argon2>::setup();
+            let client_start =
+                client_register_start(&mut client_state, password, KsfParams::default()).unwrap();
+            let request = encode_envelope(
+                ObjectKind::RegistrationRequest,
+                SuiteId::P384Argon2,
+                &client_start.response,
+            );
+            let server_start =
+                server_register_start(&mut setup.view(), &request, b"my_user").unwrap();
+            // A message in an envelope of the wrong kind is rejected
+            let response = encode_envelope(
+                ObjectKind::RegistrationRequest,
+                SuiteId::P384Argon2,
+                &server_start.response,
+            );
+            assert!(client_register_finish(
+                &mut client_state,
+                password,
+                &response,
+                Identifiers::default()
+            )
+            .is_err());
 
-// The following code is taken from version 1.1.1 of https://github.com/fizyk20/generic-array under the MIT License
-// It's a backport of some functions that allow conversion to normal arrays to the version of generic array used in opaque_ke (0.14.7)
-mod generic_array_backport {
-    use generic_array::{ArrayLength, GenericArray};
-    use typenum::{Const, ToUInt};
+            let mut client_state = ClientStateRegistration::<P384Argon2>::setup();
+            let client_start =
+                client_register_start(&mut client_state, password, KsfParams::default()).unwrap();
+            let mut client_state = ClientStateRegistration::<P384Argon2>::deserialize(
+                &client_state.serialize_envelope().unwrap(),
+            )
+            .unwrap();
+            let server_start =
+                server_register_start(&mut setup.view(), &client_start.response, b"my_user")
+                    .unwrap();
+            let client_finish = client_register_finish(
+                &mut client_state,
+                password,
+                &server_start.response,
+                Identifiers::default(),
+            )
+            .unwrap();
+            let password_file =
+                server_register_finish::<P384Argon2>(&client_finish.response).unwrap();
+            let envelope = password_file.serialize_envelope();
+            assert!(PasswordFile::<P384Argon2>::deserialize(&envelope).is_ok());
+            assert!(matches!(
+                PasswordFile::<P256Argon2>::deserialize(&envelope),
+                Err(Error::SuiteMismatch { .. })
+            ));
+        }
 
-    const unsafe fn const_transmute<A, B>(a: A) -> B {
-        if std::mem::size_of::<A>() != std::mem::size_of::<B>() {
-            panic!("Size mismatch for generic_array::const_transmute");
+        #[test]
+        fn legacy_setup() {
+            let setup = ServerSetup::<Cipher>::create();
+            let legacy = setup.0.serialize();
+
+            assert_eq!(SERVER_SETUP_SERIALIZED_LEN, setup.serialize().len());
+            assert!(ServerSetup::<Cipher>::deserialize(&legacy).is_ok());
+            assert!(ServerSetup::<P256Argon2>::deserialize(&legacy).is_err());
+
+            // The random OPRF seed can start with what looks like an envelope header
+            let mut colliding = legacy.to_vec();
+            let header = encode_envelope(ObjectKind::ServerSetup, Cipher::ID, &[]);
+            colliding[..header.len()].copy_from_slice(&header);
+            let setup = ServerSetup::<Cipher>::deserialize(&colliding).unwrap();
+            assert_eq!(colliding, setup.0.serialize().to_vec());
         }
 
-        #[repr(C)]
-        union Union<A, B> {
-            a: std::mem::ManuallyDrop<A>,
-            b: std::mem::ManuallyDrop<B>,
+        fn change_password<CS: Suite>(
+            setup: &ServerSetup<CS>,
+            password_file: &PasswordFile<CS>,
+            old_password: &[u8],
+            new_password: &[u8],
+        ) -> Result<(ClientChangePasswordFinishResult, SecretBytes), Error> {
+            let ksf = KsfParams::new(argon2::Algorithm::Argon2id, 1024, 1, 1).unwrap();
+            let mut client_state = ClientStateChangePassword::<CS>::setup();
+            let client_start =
+                client_change_password_start(&mut client_state, old_password, new_password, ksf)
+                    .unwrap();
+            let client_state = client_state.serialize_envelope().unwrap();
+            let mut client_state =
+                ClientStateChangePassword::<CS>::deserialize(&client_state).unwrap();
+            let server_start = server_change_password_start(
+                &mut setup.view(),
+                password_file,
+                &client_start.response,
+                "my_user",
+                Identifiers::default(),
+                None,
+            )
+            .unwrap();
+            let client_finish = client_change_password_finish(
+                &mut client_state,
+                old_password,
+                new_password,
+                &server_start.response,
+                password_file.ksf_params(),
+                Identifiers::default(),
+                None,
+                None,
+            )?;
+
+            Ok((client_finish, server_start.state))
         }
 
-        let a = std::mem::ManuallyDrop::new(a);
-        std::mem::ManuallyDrop::into_inner(Union { a }.b)
-    }
+        fn login<CS: Suite>(
+            setup: &ServerSetup<CS>,
+            password_file: &PasswordFile<CS>,
+            password: &[u8],
+        ) -> Result<ClientLoginFinishResult, Error> {
+            let mut client_state = ClientStateLogin::<CS>::setup();
+            let client_start = client_login_start(&mut client_state, password).unwrap();
+            let server_start = server_login_start(
+                &mut setup.view(),
+                Some(password_file),
+                &client_start.response,
+                "my_user",
+                Identifiers::default(),
+                None,
+            )
+            .unwrap();
 
-    pub trait IntoArray<N: ArrayLength<u8>> {
-        fn into_array<const U: usize>(self) -> [u8; U]
-        where
-            Const<U>: IntoArrayLength<ArrayLength = N>,
-            Self: Sized;
-    }
+            client_login_finish(
+                &mut client_state,
+                password,
+                &server_start.response,
+                password_file.ksf_params(),
+                Identifiers::default(),
+                None,
+                Some(&setup.public_key()),
+            )
+        }
+
+        #[test]
+        fn change_password_flow() {
+            let setup = ServerSetup::<P256Argon2>::create();
+            let mut client_state = ClientStateRegistration::<P256Argon2>::setup();
+            let client_start =
+                client_register_start(&mut client_state, b"old_pass", KsfParams::default())
+                    .unwrap();
+            let server_start =
+                server_register_start(&mut setup.view(), &client_start.response, b"my_user")
+                    .unwrap();
+            let client_finish = client_register_finish(
+                &mut client_state,
+                b"old_pass",
+                &server_start.response,
+                Identifiers::default(),
+            )
+            .unwrap();
+            let old_export_key = client_finish.export_key;
+            let password_file = server_register_finish(&client_finish.response).unwrap();
+
+            let (client_finish, state) =
+                change_password(&setup, &password_file, b"old_pass", b"new_pass").unwrap();
+            let server_finish = server_change_password_finish::<P256Argon2>(
+                &client_finish.response,
+                &state,
+                &StateChecks::default(),
+            )
+            .unwrap();
+            assert_eq!(*client_finish.shared_secret, *server_finish.shared_secret);
+            assert_eq!(*old_export_key, *client_finish.old_export_key);
+
+            let new_file = server_finish.password_file;
+            assert_eq!(
+                KsfParams::new(argon2::Algorithm::Argon2id, 1024, 1, 1).unwrap(),
+                new_file.ksf_params()
+            );
+            assert_eq!(
+                *client_finish.export_key,
+                *login(&setup, &new_file, b"new_pass").unwrap().export_key
+            );
+            assert!(matches!(
+                login(&setup, &new_file, b"old_pass"),
+                Err(Error::ProtocolError(ProtocolError::InvalidLoginError))
+            ));
+
+            // Without the old password the login fails before anything is uploaded
+            assert!(matches!(
+                change_password(&setup, &password_file, b"wrong_pass", b"new_pass"),
+                Err(Error::ProtocolError(ProtocolError::InvalidLoginError))
+            ));
+        }
 
-    impl<N: ArrayLength<u8>> IntoArray<N> for GenericArray<u8, N> {
-        fn into_array<const U: usize>(self) -> [u8; U]
-        where
-            Const<U>: IntoArrayLength<ArrayLength = N>,
-            Self: Sized,
-        {
-            unsafe { const_transmute(self) }
+        #[test]
+        fn change_password_authenticated() {
+            let setup = ServerSetup::<Cipher>::create();
+            let password_file = crate::test_util::gen_password_file_with_setup_and_pw(
+                &mut setup.view(),
+                b"my_user",
+                b"old_pass",
+            );
+            let (client_finish, state) =
+                change_password(&setup, &password_file, b"old_pass", b"new_pass").unwrap();
+
+            // The upload can't be replaced by one the client did not authenticate
+            let mut tampered = client_finish.response.clone();
+            let last = tampered.len() - 1;
+            tampered[last] ^= 1;
+            assert!(matches!(
+                server_change_password_finish::<Cipher>(&tampered, &state, &StateChecks::default()),
+                Err(Error::ProtocolError(ProtocolError::InvalidLoginError))
+            ));
+            assert!(matches!(
+                server_change_password_finish::<Cipher>(
+                    &client_finish.response[1..],
+                    &state,
+                    &StateChecks::default()
+                ),
+                Err(Error::ProtocolError(ProtocolError::SerializationError))
+            ));
+            assert!(server_change_password_finish::<Cipher>(
+                &client_finish.response,
+                &state,
+                &StateChecks::default()
+            )
+            .is_ok());
         }
     }
+}
 
-    pub trait IntoArrayLength {
-        /// The associated `ArrayLength`
-        type ArrayLength: ArrayLength<u8>;
-    }
+#[cfg(test)]
+pub mod test_util {
+    use super::ksf::KsfParams;
+    use super::opaque_impl::*;
+    use super::{Cipher, Identifiers};
 
-    impl<const N: usize> IntoArrayLength for Const<N>
-    where
-        Const<N>: ToUInt,
-        typenum::U<N>: ArrayLength<u8>,
-    {
-        type ArrayLength = typenum::U<N>;
+    pub fn gen_password_file_with_setup_and_pw(setup: &mut ServerSetupView, user_id: &[u8], password: &[u8]) -> PasswordFile {
+        let mut client_state = ClientStateRegistration::<Cipher>::setup();
+
+        let client_start =
+            client_register_start(&mut client_state, password, KsfParams::default()).unwrap();
+        
+        let server_start = server_register_start(setup, &client_start.response, user_id).unwrap();
+
+        let client_finish = client_register_finish(&mut client_state, password, &server_start.response, Identifiers::default()).unwrap();
+
+        server_register_finish(&client_finish.response).unwrap()
     }
 }
-
-pub(crate) use generic_array_backport::IntoArray;
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::RngCore;
+
+use crate::Error;
+
+pub const STATE_NONCE_LEN: usize = 16;
+/// Length of the creation time (unix seconds as a big-endian u64) and nonce appended to server
+/// login states.
+pub const STATE_META_LEN: usize = 8 + STATE_NONCE_LEN;
+
+/// Records the nonces of server login states passed to `server_login_finish`, so that a state
+/// can't be used twice. Implement it on top of a shared store (e.g. Redis) when logins can be
+/// finished by several processes.
+pub trait SeenNonces {
+    /// Records `nonce`, returning `false` if it was recorded before. The nonce has to be
+    /// remembered until `expires_at` (unix seconds), after which the state is rejected for its
+    /// age.
+    fn insert(&self, nonce: &[u8; STATE_NONCE_LEN], expires_at: u64) -> Result<bool, Error>;
+}
+
+/// [`SeenNonces`] kept in memory, for a single process.
+#[derive(Default)]
+pub struct MemorySeenNonces {
+    nonces: Mutex<HashMap<[u8; STATE_NONCE_LEN], u64>>,
+}
+
+impl SeenNonces for MemorySeenNonces {
+    fn insert(&self, nonce: &[u8; STATE_NONCE_LEN], expires_at: u64) -> Result<bool, Error> {
+        let now = unix_time();
+        let mut nonces = self
+            .nonces
+            .lock()
+            .map_err(|_| Error::NonceStore("lock poisoned".to_owned()))?;
+        nonces.retain(|_, expires_at| *expires_at > now);
+
+        Ok(nonces.insert(*nonce, expires_at).is_none())
+    }
+}
+
+/// Checks of the server login state done by `server_login_finish`. The default checks nothing,
+/// which is also the only way to finish states created before they had a creation time and
+/// nonce.
+#[derive(Clone, Copy, Default)]
+pub struct StateChecks<'a> {
+    /// Rejects states created longer ago with `Error::StateExpired`.
+    pub max_age: Option<Duration>,
+    /// Rejects states that have been used before with `Error::StateReplayed`. Requires a
+    /// `max_age`, without which the nonces could never be forgotten.
+    pub seen_nonces: Option<&'a dyn SeenNonces>,
+}
+
+impl StateChecks<'_> {
+    /// `meta` is `None` for states without a creation time and nonce.
+    pub(crate) fn check(&self, meta: Option<&[u8]>) -> Result<(), Error> {
+        let max_age = match (self.max_age, self.seen_nonces) {
+            (None, None) => return Ok(()),
+            (None, Some(_)) => {
+                return Err(Error::InvalidState(
+                    "checking seen nonces requires a max age",
+                ))
+            }
+            (Some(max_age), _) => max_age,
+        };
+        let meta = meta.ok_or(Error::InvalidState(
+            "the login state has no creation time and nonce",
+        ))?;
+        let mut created_at = [0u8; 8];
+        created_at.copy_from_slice(&meta[..8]);
+        let created_at = u64::from_be_bytes(created_at);
+        let mut nonce = [0u8; STATE_NONCE_LEN];
+        nonce.copy_from_slice(&meta[8..]);
+
+        let expires_at = created_at.saturating_add(max_age.as_secs());
+        if unix_time() > expires_at {
+            return Err(Error::StateExpired);
+        }
+        if let Some(seen_nonces) = self.seen_nonces {
+            if !seen_nonces.insert(&nonce, expires_at)? {
+                return Err(Error::StateReplayed);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The creation time `now` (unix seconds) followed by a nonce drawn from `rng`.
+pub(crate) fn state_meta<R: RngCore>(rng: &mut R, now: u64) -> [u8; STATE_META_LEN] {
+    let mut meta = [0u8; STATE_META_LEN];
+    meta[..8].copy_from_slice(&now.to_be_bytes());
+    rng.fill_bytes(&mut meta[8..]);
+
+    meta
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// `SystemTime::now` panics on `wasm32-unknown-unknown`, so the time is read from JavaScript.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) fn unix_time() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use crate::ErrorKind;
+
+    use super::*;
+
+    fn state_meta() -> [u8; STATE_META_LEN] {
+        super::state_meta(&mut OsRng, unix_time())
+    }
+
+    #[test]
+    fn max_age() {
+        let checks = StateChecks {
+            max_age: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+
+        assert!(checks.check(Some(&state_meta())).is_ok());
+        let mut old = state_meta();
+        old[..8].copy_from_slice(&(unix_time() - 61).to_be_bytes());
+        let err = checks.check(Some(&old)).unwrap_err();
+        assert!(matches!(err, Error::StateExpired));
+        assert_eq!(ErrorKind::InvalidState, err.kind());
+        assert!(matches!(checks.check(None), Err(Error::InvalidState(_))));
+        assert!(StateChecks::default().check(None).is_ok());
+    }
+
+    #[test]
+    fn replay() {
+        let seen_nonces = MemorySeenNonces::default();
+        let checks = StateChecks {
+            max_age: Some(Duration::from_secs(60)),
+            seen_nonces: Some(&seen_nonces),
+        };
+        let meta = state_meta();
+
+        assert!(checks.check(Some(&meta)).is_ok());
+        assert!(checks.check(Some(&state_meta())).is_ok());
+        assert!(matches!(
+            checks.check(Some(&meta)),
+            Err(Error::StateReplayed)
+        ));
+
+        // The nonces would have to be kept forever
+        let checks = StateChecks {
+            max_age: None,
+            seen_nonces: Some(&seen_nonces),
+        };
+        assert!(matches!(
+            checks.check(Some(&state_meta())),
+            Err(Error::InvalidState(_))
+        ));
+    }
+}
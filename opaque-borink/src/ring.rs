@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+
+use opaque_ke::errors::ProtocolError;
+use zeroize::Zeroizing;
+
+use crate::encoded::{encode_envelope, open_envelope, ObjectKind};
+use crate::opaque_impl::{PasswordFile, ServerSetup, ServerSetupView};
+use crate::secret::SecretBytes;
+use crate::suite::{tag, untag, Suite};
+use crate::{Cipher, Error};
+
+/// Identifies a setup in a [`ServerSetupRing`].
+pub type KeyId = u32;
+
+/// Key id assumed for password files that do not record one, i.e. those registered before the
+/// ring was introduced. Use it for the setup they were created with.
+pub const LEGACY_KEY_ID: KeyId = 0;
+
+/// Several server setups under their key id, for rotating the setup without invalidating the
+/// password files created with the previous ones.
+///
+/// New registrations use the current setup, after which its key id is recorded in the password
+/// file using `PasswordFile::set_key_id`. At login, [`ServerSetupRing::view_for`] picks the setup
+/// the password file was registered with.
+pub struct ServerSetupRing<CS: Suite = Cipher> {
+    setups: BTreeMap<KeyId, ServerSetup<CS>>,
+    current: KeyId,
+}
+
+impl<CS: Suite> ServerSetupRing<CS> {
+    pub fn new(key_id: KeyId, setup: ServerSetup<CS>) -> Self {
+        Self {
+            setups: BTreeMap::from([(key_id, setup)]),
+            current: key_id,
+        }
+    }
+
+    /// Creates a new setup under `key_id` and makes it the current one.
+    pub fn rotate(&mut self, key_id: KeyId) -> Result<(), Error> {
+        self.insert(key_id, ServerSetup::create())?;
+        self.current = key_id;
+
+        Ok(())
+    }
+
+    /// Adds a setup without making it current.
+    pub fn insert(&mut self, key_id: KeyId, setup: ServerSetup<CS>) -> Result<(), Error> {
+        if self.setups.contains_key(&key_id) {
+            return Err(Error::DuplicateKeyId(key_id));
+        }
+        self.setups.insert(key_id, setup);
+
+        Ok(())
+    }
+
+    /// Removes a retired setup. Logins with password files registered under it will fail.
+    pub fn remove(&mut self, key_id: KeyId) -> Result<ServerSetup<CS>, Error> {
+        if key_id == self.current {
+            return Err(Error::InvalidState("the current setup cannot be removed"));
+        }
+
+        self.setups
+            .remove(&key_id)
+            .ok_or(Error::UnknownKeyId(key_id))
+    }
+
+    pub fn set_current(&mut self, key_id: KeyId) -> Result<(), Error> {
+        if !self.setups.contains_key(&key_id) {
+            return Err(Error::UnknownKeyId(key_id));
+        }
+        self.current = key_id;
+
+        Ok(())
+    }
+
+    pub fn current_key_id(&self) -> KeyId {
+        self.current
+    }
+
+    /// The setup to use for new registrations.
+    pub fn current(&self) -> &ServerSetup<CS> {
+        &self.setups[&self.current]
+    }
+
+    pub fn get(&self, key_id: KeyId) -> Option<&ServerSetup<CS>> {
+        self.setups.get(&key_id)
+    }
+
+    pub fn key_ids(&self) -> impl Iterator<Item = KeyId> + '_ {
+        self.setups.keys().copied()
+    }
+
+    /// View of the setup `password_file` was registered with, for `server_login_start`. Password
+    /// files without a key id use [`LEGACY_KEY_ID`]. Unknown users (`None`) get the current
+    /// setup.
+    pub fn view_for(
+        &self,
+        password_file: Option<&PasswordFile<CS>>,
+    ) -> Result<ServerSetupView<'_, CS>, Error> {
+        let key_id = match password_file {
+            Some(password_file) => password_file.key_id().unwrap_or(LEGACY_KEY_ID),
+            None => self.current,
+        };
+        let setup = self
+            .setups
+            .get(&key_id)
+            .ok_or(Error::UnknownKeyId(key_id))?;
+
+        Ok(setup.view())
+    }
+
+    /// The suite tag, followed by the current key id and then every key id and its serialized
+    /// setup, prefixed by its length.
+    pub fn serialize(&self) -> SecretBytes {
+        let mut entries = Zeroizing::new(Vec::new());
+        for (key_id, setup) in &self.setups {
+            let setup = setup.serialize();
+            entries.extend_from_slice(&key_id.to_be_bytes());
+            entries.extend_from_slice(&(setup.len() as u16).to_be_bytes());
+            entries.extend_from_slice(&setup);
+        }
+
+        tag::<CS>(&[&self.current.to_be_bytes(), &entries]).into()
+    }
+
+    pub fn serialize_envelope(&self) -> SecretBytes {
+        encode_envelope(ObjectKind::ServerSetupRing, CS::ID, &self.serialize()).into()
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes = open_envelope::<CS>(bytes, ObjectKind::ServerSetupRing, &[])?;
+        // Rings have no untagged legacy form
+        let (bytes, _) = untag::<CS>(bytes, usize::MAX)?;
+        let (current, mut bytes) = split_u32(bytes)?;
+
+        let mut setups = BTreeMap::new();
+        while !bytes.is_empty() {
+            let (key_id, rest) = split_u32(bytes)?;
+            if rest.len() < 2 {
+                return Err(ProtocolError::SerializationError.into());
+            }
+            let (len, rest) = rest.split_at(2);
+            let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+            if rest.len() < len {
+                return Err(ProtocolError::SerializationError.into());
+            }
+            let (setup, rest) = rest.split_at(len);
+            if setups
+                .insert(key_id, ServerSetup::<CS>::deserialize(setup)?)
+                .is_some()
+            {
+                return Err(Error::DuplicateKeyId(key_id));
+            }
+            bytes = rest;
+        }
+        if !setups.contains_key(&current) {
+            return Err(Error::UnknownKeyId(current));
+        }
+
+        Ok(Self { setups, current })
+    }
+}
+
+impl<CS: Suite> From<ServerSetup<CS>> for ServerSetupRing<CS> {
+    /// A ring containing only `setup` under [`LEGACY_KEY_ID`], for migrating from a single setup.
+    fn from(setup: ServerSetup<CS>) -> Self {
+        Self::new(LEGACY_KEY_ID, setup)
+    }
+}
+
+fn split_u32(bytes: &[u8]) -> Result<(u32, &[u8]), Error> {
+    if bytes.len() < 4 {
+        return Err(ProtocolError::SerializationError.into());
+    }
+    let (int, rest) = bytes.split_at(4);
+
+    Ok((u32::from_be_bytes([int[0], int[1], int[2], int[3]]), rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{
+        client_login_finish, client_login_start, client_register_finish, client_register_start,
+        ClientStateLogin, ClientStateRegistration,
+    };
+    use crate::ksf::KsfParams;
+    use crate::server::{
+        server_login_finish, server_login_start, server_register_finish, server_register_start,
+        StateChecks,
+    };
+    use crate::suite::P256Argon2;
+    use crate::Identifiers;
+
+    use super::*;
+
+    fn register(ring: &ServerSetupRing, password: &[u8]) -> PasswordFile {
+        let ksf = KsfParams::new(argon2::Algorithm::Argon2id, 1024, 1, 1).unwrap();
+        let mut client_state = ClientStateRegistration::<Cipher>::setup();
+        let client_start = client_register_start(&mut client_state, password, ksf).unwrap();
+        let server_start = server_register_start(
+            &mut ring.current().view(),
+            &client_start.response,
+            b"my_user",
+        )
+        .unwrap();
+        let client_finish = client_register_finish(
+            &mut client_state,
+            password,
+            &server_start.response,
+            Identifiers::default(),
+        )
+        .unwrap();
+        let mut password_file = server_register_finish::<Cipher>(&client_finish.response).unwrap();
+        password_file.set_key_id(Some(ring.current_key_id()));
+
+        PasswordFile::deserialize(&password_file.serialize()).unwrap()
+    }
+
+    fn login(ring: &ServerSetupRing, password_file: &PasswordFile, password: &[u8]) -> bool {
+        let mut client_state = ClientStateLogin::<Cipher>::setup();
+        let client_start = client_login_start(&mut client_state, password).unwrap();
+        let server_start = server_login_start(
+            &mut ring.view_for(Some(password_file)).unwrap(),
+            Some(password_file),
+            &client_start.response,
+            "my_user",
+            Identifiers::default(),
+            None,
+        )
+        .unwrap();
+        let Ok(client_finish) = client_login_finish(
+            &mut client_state,
+            password,
+            &server_start.response,
+            password_file.ksf_params(),
+            Identifiers::default(),
+            None,
+            None,
+        ) else {
+            return false;
+        };
+
+        server_login_finish::<Cipher>(
+            &client_finish.response,
+            &server_start.state,
+            &StateChecks::default(),
+        )
+        .is_ok()
+    }
+
+    #[test]
+    fn rotation() {
+        let password = b"my_pass";
+        let mut ring = ServerSetupRing::from(ServerSetup::<Cipher>::create());
+        let old_file = register(&ring, password);
+        assert_eq!(Some(LEGACY_KEY_ID), old_file.key_id());
+
+        ring.rotate(1).unwrap();
+        let ring = ServerSetupRing::<Cipher>::deserialize(&ring.serialize()).unwrap();
+        assert_eq!(1, ring.current_key_id());
+        assert_eq!(vec![0, 1], ring.key_ids().collect::<Vec<_>>());
+        let new_file = register(&ring, password);
+        assert_eq!(Some(1), new_file.key_id());
+
+        assert!(login(&ring, &old_file, password));
+        assert!(login(&ring, &new_file, password));
+    }
+
+    #[test]
+    fn ring_errors() {
+        let mut ring = ServerSetupRing::new(3, ServerSetup::<P256Argon2>::create());
+
+        assert!(matches!(ring.rotate(3), Err(Error::DuplicateKeyId(3))));
+        assert!(matches!(ring.set_current(4), Err(Error::UnknownKeyId(4))));
+        assert!(matches!(ring.remove(3), Err(Error::InvalidState(_))));
+        ring.rotate(4).unwrap();
+        assert!(ring.remove(3).is_ok());
+
+        let envelope = ring.serialize_envelope();
+        let ring = ServerSetupRing::<P256Argon2>::deserialize(&envelope).unwrap();
+        assert_eq!(4, ring.current_key_id());
+        assert!(ServerSetupRing::<Cipher>::deserialize(&envelope).is_err());
+        assert!(ServerSetup::<P256Argon2>::deserialize(&envelope).is_err());
+    }
+}
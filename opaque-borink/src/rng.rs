@@ -0,0 +1,77 @@
+//! The random number generator of the client states and server setup views. It is the
+//! thread-local generator, unless another one is passed to the `with_rng` constructors of the
+//! `test-vectors` feature, e.g. a seeded one for reproducing test vectors.
+
+#[cfg(any(test, feature = "test-vectors"))]
+use std::cell::RefCell;
+#[cfg(any(test, feature = "test-vectors"))]
+use std::rc::Rc;
+
+use rand::rngs::ThreadRng;
+use rand::{thread_rng, CryptoRng, RngCore};
+
+/// Any cryptographically secure random number generator, which the `with_rng` constructors
+/// accept.
+#[cfg(any(test, feature = "test-vectors"))]
+pub trait CryptoRngCore: CryptoRng + RngCore {}
+
+#[cfg(any(test, feature = "test-vectors"))]
+impl<R: CryptoRng + RngCore> CryptoRngCore for R {}
+
+/// Clones share the generator, as clones of `ThreadRng` do, so that the output of a seeded
+/// generator does not depend on how often a state or view is cloned.
+#[derive(Clone)]
+pub(crate) enum StateRng {
+    Thread(ThreadRng),
+    #[cfg(any(test, feature = "test-vectors"))]
+    Custom(Rc<RefCell<dyn CryptoRngCore>>),
+}
+
+impl StateRng {
+    #[cfg(any(test, feature = "test-vectors"))]
+    pub(crate) fn custom<R: CryptoRngCore + 'static>(rng: R) -> Self {
+        Self::Custom(Rc::new(RefCell::new(rng)))
+    }
+}
+
+impl Default for StateRng {
+    fn default() -> Self {
+        Self::Thread(thread_rng())
+    }
+}
+
+impl RngCore for StateRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            Self::Thread(rng) => rng.next_u32(),
+            #[cfg(any(test, feature = "test-vectors"))]
+            Self::Custom(rng) => rng.borrow_mut().next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            Self::Thread(rng) => rng.next_u64(),
+            #[cfg(any(test, feature = "test-vectors"))]
+            Self::Custom(rng) => rng.borrow_mut().next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            Self::Thread(rng) => rng.fill_bytes(dest),
+            #[cfg(any(test, feature = "test-vectors"))]
+            Self::Custom(rng) => rng.borrow_mut().fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match self {
+            Self::Thread(rng) => rng.try_fill_bytes(dest),
+            #[cfg(any(test, feature = "test-vectors"))]
+            Self::Custom(rng) => rng.borrow_mut().try_fill_bytes(dest),
+        }
+    }
+}
+
+impl CryptoRng for StateRng {}
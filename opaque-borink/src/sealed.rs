@@ -0,0 +1,251 @@
+use std::time::Duration;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use opaque_ke::errors::ProtocolError;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::opaque_impl::{server_login_finish, ServerLoginFinishResult};
+use crate::replay::{unix_time, StateChecks};
+use crate::secret::SecretBytes;
+use crate::suite::Suite;
+use crate::Error;
+
+pub const STATE_KEY_LEN: usize = 32;
+
+const SEALED_STATE_VERSION: u8 = 1;
+/// Format version and expiry time, which are authenticated along with the user id.
+const SEALED_HEADER_LEN: usize = 1 + 8;
+const NONCE_LEN: usize = 24;
+
+/// Key for sealing server login states with XChaCha20-Poly1305, so that they can be sent to the
+/// client between `server_login_start` and `server_login_finish` instead of being stored. It must
+/// be kept secret and be shared by all servers that finish logins.
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub struct StateKey([u8; STATE_KEY_LEN]);
+
+impl StateKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; STATE_KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+
+        Self(key)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let key = bytes
+            .try_into()
+            .map_err(|_| ProtocolError::SerializationError)?;
+
+        Ok(Self(key))
+    }
+
+    pub fn to_bytes(&self) -> SecretBytes {
+        self.0.as_slice().into()
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+/// Encrypts the `state` returned by `server_login_start`, binding it to `user_id` and letting it
+/// expire after `ttl`. Unsealing with another key or user id, after it expired or after it was
+/// modified fails.
+pub fn seal_login_state(
+    key: &StateKey,
+    state: &[u8],
+    user_id: &str,
+    ttl: Duration,
+) -> Result<Vec<u8>, Error> {
+    let expires_at = unix_time().saturating_add(ttl.as_secs());
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut sealed = Vec::with_capacity(SEALED_HEADER_LEN + NONCE_LEN + state.len() + 16);
+    sealed.push(SEALED_STATE_VERSION);
+    sealed.extend_from_slice(&expires_at.to_be_bytes());
+    let ciphertext = key
+        .cipher()
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: state,
+                aad: &associated_data(&sealed, user_id),
+            },
+        )
+        .map_err(|_| Error::InvalidSealedState)?;
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+
+    Ok(sealed)
+}
+
+/// Decrypts a state sealed by [`seal_login_state`], which can then be passed to
+/// `server_login_finish`.
+pub fn unseal_login_state(
+    key: &StateKey,
+    sealed: &[u8],
+    user_id: &str,
+) -> Result<SecretBytes, Error> {
+    if sealed.len() < SEALED_HEADER_LEN + NONCE_LEN {
+        return Err(ProtocolError::SerializationError.into());
+    }
+    let (header, rest) = sealed.split_at(SEALED_HEADER_LEN);
+    if header[0] != SEALED_STATE_VERSION {
+        return Err(ProtocolError::SerializationError.into());
+    }
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let state: SecretBytes = key
+        .cipher()
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &associated_data(header, user_id),
+            },
+        )
+        .map_err(|_| Error::InvalidSealedState)?
+        .into();
+
+    // Only trusted once authenticated
+    let mut expires_at = [0u8; 8];
+    expires_at.copy_from_slice(&header[1..]);
+    if unix_time() >= u64::from_be_bytes(expires_at) {
+        return Err(Error::StateExpired);
+    }
+
+    Ok(state)
+}
+
+/// `server_login_finish` for a state sealed by [`seal_login_state`]. Use the `seen_nonces` of the
+/// `checks` to prevent a sealed state from being used twice before it expires.
+pub fn server_login_finish_sealed<CS: Suite>(
+    login_finish_request: &[u8],
+    sealed_state: &[u8],
+    key: &StateKey,
+    user_id: &str,
+    checks: &StateChecks,
+) -> Result<ServerLoginFinishResult, Error> {
+    let state = unseal_login_state(key, sealed_state, user_id)?;
+
+    server_login_finish::<CS>(login_finish_request, &state, checks)
+}
+
+fn associated_data(header: &[u8], user_id: &str) -> Vec<u8> {
+    [header, user_id.as_bytes()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{client_login_finish, client_login_start, ClientStateLogin};
+    use crate::ksf::KsfParams;
+    use crate::replay::MemorySeenNonces;
+    use crate::server::{server_login_start, ServerSetup};
+    use crate::test_util::gen_password_file_with_setup_and_pw;
+    use crate::{Cipher, ErrorKind, Identifiers};
+
+    use super::*;
+
+    #[test]
+    fn sealed_login() {
+        let setup = ServerSetup::<Cipher>::create();
+        let password_file =
+            gen_password_file_with_setup_and_pw(&mut setup.view(), b"my_user", b"my_pass");
+        let key = StateKey::from_bytes(&StateKey::generate().to_bytes()).unwrap();
+
+        let mut client_state = ClientStateLogin::<Cipher>::setup();
+        let client_start = client_login_start(&mut client_state, b"my_pass").unwrap();
+        let server_start = server_login_start(
+            &mut setup.view(),
+            Some(&password_file),
+            &client_start.response,
+            "my_user",
+            Identifiers::default(),
+            None,
+        )
+        .unwrap();
+        let sealed = seal_login_state(
+            &key,
+            &server_start.state,
+            "my_user",
+            Duration::from_secs(60),
+        )
+        .unwrap();
+        assert_eq!(
+            *server_start.state,
+            *unseal_login_state(&key, &sealed, "my_user").unwrap()
+        );
+
+        let client_finish = client_login_finish(
+            &mut client_state,
+            b"my_pass",
+            &server_start.response,
+            KsfParams::default(),
+            Identifiers::default(),
+            None,
+            None,
+        )
+        .unwrap();
+        let seen_nonces = MemorySeenNonces::default();
+        let checks = StateChecks {
+            max_age: Some(Duration::from_secs(60)),
+            seen_nonces: Some(&seen_nonces),
+        };
+        let server_finish = server_login_finish_sealed::<Cipher>(
+            &client_finish.response,
+            &sealed,
+            &key,
+            "my_user",
+            &checks,
+        )
+        .unwrap();
+        assert_eq!(*client_finish.shared_secret, *server_finish.shared_secret);
+        // Sealed states are not stored, so only the seen nonces prevent their reuse
+        assert!(matches!(
+            server_login_finish_sealed::<Cipher>(
+                &client_finish.response,
+                &sealed,
+                &key,
+                "my_user",
+                &checks,
+            ),
+            Err(Error::StateReplayed)
+        ));
+    }
+
+    #[test]
+    fn sealed_checks() {
+        let key = StateKey::generate();
+        let state = [1u8; 16];
+        let sealed = seal_login_state(&key, &state, "my_user", Duration::from_secs(60)).unwrap();
+
+        let err = unseal_login_state(&key, &sealed, "other_user")
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidSealedState));
+        assert_eq!(ErrorKind::InvalidLogin, err.kind());
+        assert!(matches!(
+            unseal_login_state(&StateKey::generate(), &sealed, "my_user"),
+            Err(Error::InvalidSealedState)
+        ));
+
+        // Extending the expiry time invalidates the seal
+        let mut tampered = sealed.clone();
+        tampered[1] ^= 1;
+        assert!(matches!(
+            unseal_login_state(&key, &tampered, "my_user"),
+            Err(Error::InvalidSealedState)
+        ));
+        assert!(unseal_login_state(&key, &sealed[..SEALED_HEADER_LEN], "my_user").is_err());
+
+        let expired = seal_login_state(&key, &state, "my_user", Duration::ZERO).unwrap();
+        let err = unseal_login_state(&key, &expired, "my_user").err().unwrap();
+        assert!(matches!(err, Error::StateExpired));
+        assert_eq!(ErrorKind::InvalidState, err.kind());
+
+        assert!(StateKey::from_bytes(&[0u8; 16]).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::ops::{Deref, DerefMut};
+
+use subtle::{Choice, ConstantTimeEq};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Bytes of a secret, such as a shared secret, export key or serialized setup or state, which are
+/// overwritten with zeros when dropped. It dereferences to `[u8]`, but does not implement `Debug`
+/// so that it does not end up in logs by accident. Equality is checked in constant time.
+///
+/// The opaque-ke types inside the states and setups zeroize themselves.
+#[derive(Clone, Default)]
+pub struct SecretBytes(Vec<u8>);
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl DerefMut for SecretBytes {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl AsRef<[u8]> for SecretBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for SecretBytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl ConstantTimeEq for SecretBytes {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.as_slice().ct_eq(other.0.as_slice())
+    }
+}
+
+impl PartialEq for SecretBytes {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for SecretBytes {}
+
+impl Zeroize for SecretBytes {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for SecretBytes {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zeroize() {
+        let mut secret = SecretBytes::from(vec![1u8, 2, 3]);
+        assert_eq!(&[1, 2, 3], &*secret);
+
+        secret.zeroize();
+        assert!(secret.is_empty());
+    }
+
+    #[test]
+    fn constant_time_eq() {
+        let secret = SecretBytes::from(vec![1u8, 2, 3]);
+
+        assert!(secret == SecretBytes::from(&[1u8, 2, 3][..]));
+        assert!(secret != SecretBytes::from(&[1u8, 2, 4][..]));
+        assert!(secret != SecretBytes::from(&[1u8, 2][..]));
+    }
+}
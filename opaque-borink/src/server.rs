@@ -1,18 +1,34 @@
+pub use crate::ksf::KsfParams;
 pub use crate::opaque_impl::{
-    server_login_finish, server_login_start, server_register_finish, server_register_start,
+    server_change_password_finish, server_change_password_start, server_login_finish,
+    server_login_start, server_register_finish, server_register_start,
 };
 pub use crate::opaque_impl::{
-    PasswordFile, ServerLoginFinishResult, ServerLoginStartResult, ServerRegistrationStartResult,
-    ServerSetup, ServerSetupView
+    PasswordFile, ServerChangePasswordFinishResult, ServerLoginFinishResult,
+    ServerLoginStartResult, ServerRegistrationStartResult, ServerSetup, ServerSetupView,
 };
 pub use crate::opaque_impl::{
-    LOGIN_SERVER_MESSAGE_LEN, LOGIN_SERVER_STATE_LEN, PASSWORD_FILE_LEN,
-    REGISTER_SERVER_MESSAGE_LEN, SERVER_SETUP_LEN, SHARED_SECRET_LEN,
+    LOGIN_SERVER_MESSAGE_LEN, LOGIN_SERVER_STATE_LEN, LOGIN_SERVER_STATE_SERIALIZED_LEN,
+    PASSWORD_FILE_LEN, PASSWORD_FILE_REGISTRATION_LEN, PASSWORD_FILE_SERIALIZED_LEN,
+    REGISTER_SERVER_MESSAGE_LEN, SERVER_PUBLIC_KEY_LEN, SERVER_SETUP_LEN,
+    SERVER_SETUP_SERIALIZED_LEN, SHARED_SECRET_LEN,
 };
+pub use crate::replay::{MemorySeenNonces, SeenNonces, StateChecks};
+pub use crate::ring::{KeyId, ServerSetupRing, LEGACY_KEY_ID};
+pub use crate::sealed::{
+    seal_login_state, server_login_finish_sealed, unseal_login_state, StateKey, STATE_KEY_LEN,
+};
+pub use crate::Identifiers;
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use rand_chacha::rand_core::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
     use crate::encoded::{decode_string, encode_bytes};
+    use crate::{Cipher, Error};
 
     use super::*;
 
@@ -24,25 +40,26 @@ mod tests {
         let cred_id = "someperson";
 
         let setup = decode_string(setup).unwrap();
-        let setup = ServerSetup::deserialize(&setup).unwrap();
+        let setup = ServerSetup::<Cipher>::deserialize(&setup).unwrap();
         let message = decode_string(message).unwrap();
 
         let response =
             server_register_start(&mut setup.view(), &message, cred_id.as_bytes()).unwrap();
-        println!("{}", encode_bytes(&response.response));
-        // example response
-        // fDCnRbPyYdSCw_6cFCDzo5Zcd5OwV2TnWNg43eWQIyqASLH7HrrwUUQdYwcPA8Bigtj_ISL-GC9iHKheKl0rew
+        assert_eq!(
+            "fDCnRbPyYdSCw_6cFCDzo5Zcd5OwV2TnWNg43eWQIyqASLH7HrrwUUQdYwcPA8Bigtj_ISL-GC9iHKheKl0rew",
+            encode_bytes(&response.response)
+        );
     }
 
     #[test]
     fn test_server_register_finish() {
+        // An upload without KSF parameters gives the same file as a legacy one
         let client_message = "LJ0rg3mSZ-x1tDbobI0xvroBjAPQ5fnAgrnEmxc67giA0XDjR8pJaOuNGlWtRku5Hk57yBlL6YrjBUQJ--7OMhPZra40WvmWSu7yT8s-CBAsE0jobWK-9qXk3xDv7TlK-g_TF3JzR3s8MntBWjIuN5Ii7Le93coLGLvm7xjQtuYHbszz3HBv-gBu_xlj7YitpgyQzYpcJGslbezqxEvZz4Jz0R64np94JBDibI7syTw13ZJ74tbjWiJbvwvKb5a-";
-        let password_file =
-            server_register_finish(&decode_string(client_message).unwrap()).unwrap();
-        println!("{}", encode_bytes(&password_file.serialize()))
+        let client_message = decode_string(client_message).unwrap();
+        let password_file = server_register_finish::<Cipher>(&client_message).unwrap();
+        let legacy = PasswordFile::<Cipher>::deserialize(&client_message).unwrap();
 
-        // example file:
-        // LJ0rg3mSZ-x1tDbobI0xvroBjAPQ5fnAgrnEmxc67giA0XDjR8pJaOuNGlWtRku5Hk57yBlL6YrjBUQJ--7OMhPZra40WvmWSu7yT8s-CBAsE0jobWK-9qXk3xDv7TlK-g_TF3JzR3s8MntBWjIuN5Ii7Le93coLGLvm7xjQtuYHbszz3HBv-gBu_xlj7YitpgyQzYpcJGslbezqxEvZz4Jz0R64np94JBDibI7syTw13ZJ74tbjWiJbvwvKb5a-
+        assert_eq!(*legacy.serialize(), *password_file.serialize());
     }
 
     #[test]
@@ -52,20 +69,32 @@ mod tests {
         // password 'clientiele'
         let password_file = "LJ0rg3mSZ-x1tDbobI0xvroBjAPQ5fnAgrnEmxc67giA0XDjR8pJaOuNGlWtRku5Hk57yBlL6YrjBUQJ--7OMhPZra40WvmWSu7yT8s-CBAsE0jobWK-9qXk3xDv7TlK-g_TF3JzR3s8MntBWjIuN5Ii7Le93coLGLvm7xjQtuYHbszz3HBv-gBu_xlj7YitpgyQzYpcJGslbezqxEvZz4Jz0R64np94JBDibI7syTw13ZJ74tbjWiJbvwvKb5a-";
         let cred_id = "someperson";
-        let setup = ServerSetup::deserialize(&decode_string(setup).unwrap()).unwrap();
+        let setup = ServerSetup::<Cipher>::deserialize(&decode_string(setup).unwrap()).unwrap();
         let password_file =
-            PasswordFile::deserialize(&decode_string(password_file).unwrap()).unwrap();
+            PasswordFile::<Cipher>::deserialize(&decode_string(password_file).unwrap()).unwrap();
         let client_message = decode_string(client_message).unwrap();
-        let result =
-            server_login_start(&mut setup.view(), &password_file, &client_message, cred_id)
-                .unwrap();
+        // A fixed rng and time make the response and state reproducible
+        let mut view = setup
+            .view_with_rng(ChaCha20Rng::from_seed([1; 32]))
+            .at_time(1_767_225_600);
+        let result = server_login_start(
+            &mut view,
+            Some(&password_file),
+            &client_message,
+            cred_id,
+            Identifiers::default(),
+            None,
+        )
+        .unwrap();
 
-        println!("resp={}", encode_bytes(&result.response));
-        println!("state={}", encode_bytes(&result.state));
-        // example response
-        // lskLi18T8NM-WjY926___29u0RoY0XcKAz8-Wzu9gRMYWfgTuEk5qx4ZF6OZkTfpM_eufiKYIoKK2HNOTUwSf-bUsZRi9vydqe2yB3Wz5y3TiWI6CkVzACIFfbKynKGg0DQ4Sr5KYhsnMTzoF1Me27oq5sONK-R1muZ8JZpGXMB8l5mllx8-jfqFfe-8EEDIH0vyi9nzBKbzZSyexPiI00js1Vo5WU55jFWWdMldTg67WhPTgfITmgoGr-bQp-6wdwJGva12wMkvwFPptzk-0TMMu04YxIRzjC3OoKNxKtT8iOPTpq6SHFnVoMq3hwsYVFXxim36iickj0BzHeqebWVoo3FV9Da-ph8i6a7sKNGpe4Q4wN-0WpBgMurTkwvwcvhUCGMYvde0j7u1QOKDI_UjA9jeTlASlQHSmu0se7E
-        // example state
-        // B552YqssmUw1OOCGiXnnJB51DwX38aYMhxTl7elzLHbnVlX1cXdlXcT2nUlU3gw3IyH-6PsAhGXDv-X20Knt3d6PlUtCThpEuiH1RxehA1u9R_OBS8ctVeeHLHhzNys4vLeWBQzHh_-sW3erjRuMBUxQQwMcgQl-4Kh_RWfIp6M8a3A1fQVtPc3V0PdNwFH9pkn26_03KwZSi7POpigdJiOHZr9fje88PY_zv5MZxb_ohiblddOWYzwYlOmNidkI
+        assert_eq!(
+            "lskLi18T8NM-WjY926___29u0RoY0XcKAz8-Wzu9gRMCPzcgOiR2xCVmphzFXDyoddu0zEHA3reJ-Oe_iBg2OFmC2pCazD--ALbu0uXIgKQTwFEoYDwZs8m0MUxnN-R9Bs9sinHc8zS2wNfRsRyT3PwgITzjsAVDz3C-0gJ9aVuE-RDv3VdDmrO8HiQ6nXar8kKUhjfp9pqiW7XJmgOj3J7MkqyT8UAr_viotl8matyONiTspZy4RdssF6fZ_PCKASdN0e5SFsIE-2mNrqRbUumLbw_dBG3MOoa7B5428CRmwiniPjEWZzTS_9KcLj1ISJ8wZ9z-OAvscsM8wGG8AVgqsEbaglzu_hb00dcUEf5cPWrziQBwIO-VMnltIyKKz7sKOVSjwROsOOBmj2NVoflp0dQOx03aaXHjfARRGbw",
+            encode_bytes(&result.response)
+        );
+        assert_eq!(
+            "AcDj5ONMSZHGT0gpUWOIJqC4OQzEtxm_rn6kDWphItWZ31j-8eOu1HT4nJuCPR1FnLbmBLpsIPxP5sh_TmYQVlXzJlXFShsj3lMTsL0zJv4nGrFjiRIK4jMPsvHCsxdq4enlmSl3WHiztZ9qKHl57qZ1zzUmXfcnSkjrrdJXErrjvSQ3vUU2q3x4pdDc1EKySgGVrUdc9ergyTpD85Bu44q4KQmeFcR_HDOKwLH_WenY0eYzJgQJuLYDjyk3z8swfQAAAABpVbkAFH5Lh11Zqe9DK45FsEqYxA",
+            encode_bytes(&result.state)
+        );
     }
 
     #[test]
@@ -75,9 +104,21 @@ mod tests {
         let state = "B552YqssmUw1OOCGiXnnJB51DwX38aYMhxTl7elzLHbnVlX1cXdlXcT2nUlU3gw3IyH-6PsAhGXDv-X20Knt3d6PlUtCThpEuiH1RxehA1u9R_OBS8ctVeeHLHhzNys4vLeWBQzHh_-sW3erjRuMBUxQQwMcgQl-4Kh_RWfIp6M8a3A1fQVtPc3V0PdNwFH9pkn26_03KwZSi7POpigdJiOHZr9fje88PY_zv5MZxb_ohiblddOWYzwYlOmNidkI";
         let state = decode_string(state).unwrap();
         let client_message = decode_string(client_message).unwrap();
-        let session = server_login_finish(&client_message, &state).unwrap();
+        let session =
+            server_login_finish::<Cipher>(&client_message, &state, &StateChecks::default())
+                .unwrap();
         let session = encode_bytes(&session.shared_secret);
         let expected_key = "PGtwNX0FbT3N1dD3TcBR_aZJ9uv9NysGUouzzqYoHSYjh2a_X43vPD2P87-TGcW_6IYm5XXTlmM8GJTpjYnZCA";
         assert_eq!(expected_key, session);
+
+        // Untagged states have no creation time to check
+        let checks = StateChecks {
+            max_age: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            server_login_finish::<Cipher>(&client_message, &state, &checks),
+            Err(Error::InvalidState(_))
+        ));
     }
 }
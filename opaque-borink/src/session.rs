@@ -0,0 +1,150 @@
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+use subtle::ConstantTimeEq;
+
+use crate::client::ClientLoginFinishResult;
+use crate::secret::SecretBytes;
+use crate::server::ServerLoginFinishResult;
+use crate::Error;
+
+/// HKDF-SHA512 can derive at most 255 blocks of 64 bytes.
+pub const MAX_DERIVED_KEY_LEN: usize = 255 * 64;
+
+pub const SESSION_TAG_LEN: usize = 16;
+
+const SESSION_TAG_LABEL: &[u8] = b"opaque-borink session tag";
+
+/// Derives a key of `len` bytes from the `shared_secret` of a login, using HKDF-SHA512 without a
+/// salt and with the `label` as info. Keys derived using different labels (e.g. "auth-token" and
+/// "channel-encryption") are independent, and both sides of a login derive the same keys.
+pub fn derive_key(shared_secret: &[u8], label: &[u8], len: usize) -> Result<SecretBytes, Error> {
+    let mut key = SecretBytes::from(vec![0u8; len]);
+    Hkdf::<Sha512>::new(None, shared_secret)
+        .expand(label, &mut key)
+        .map_err(|_| Error::InvalidKeyLength(len))?;
+
+    Ok(key)
+}
+
+/// Identifier of the session of a login, which both sides compute from the shared secret: the
+/// HMAC-SHA512 of a fixed label under the shared secret, truncated to [`SESSION_TAG_LEN`] bytes.
+/// It reveals nothing about the shared secret or the keys derived from it, so it can be used as
+/// a session handle in cookies or logs, and comparing the tags of both sides confirms they
+/// derived the same key.
+pub fn session_tag(shared_secret: &[u8]) -> Vec<u8> {
+    let mut mac =
+        Hmac::<Sha512>::new_from_slice(shared_secret).expect("HMAC takes keys of any length");
+    mac.update(SESSION_TAG_LABEL);
+
+    mac.finalize().into_bytes()[..SESSION_TAG_LEN].to_vec()
+}
+
+/// Compares secrets, such as shared secrets, derived keys or session tags, in constant time, so
+/// that the time taken does not reveal how many leading bytes are equal. Only the lengths, which
+/// are not secret, are compared in variable time.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Whether both shared secrets are equal, in constant time.
+pub fn verify_session_key(a: &[u8], b: &[u8]) -> bool {
+    constant_time_eq(a, b)
+}
+
+/// Whether `tag` is the [`session_tag`] of `shared_secret`, e.g. one sent by the other side to
+/// confirm it derived the same key. Compared in constant time.
+pub fn verify_session_tag(shared_secret: &[u8], tag: &[u8]) -> bool {
+    constant_time_eq(&session_tag(shared_secret), tag)
+}
+
+impl<M> ClientLoginFinishResult<M> {
+    /// See [`session_tag`].
+    pub fn session_tag(&self) -> Vec<u8> {
+        session_tag(&self.shared_secret)
+    }
+}
+
+impl ServerLoginFinishResult {
+    /// See [`session_tag`].
+    pub fn session_tag(&self) -> Vec<u8> {
+        session_tag(&self.shared_secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoded::encode_bytes;
+
+    use super::*;
+
+    /// The bindings are tested against the same vectors.
+    #[test]
+    fn derive_key_vectors() {
+        let shared_secret: Vec<u8> = (0..64).collect();
+
+        assert_eq!(
+            "2HW-ddl4mXN0csufO1dyEQpqzmVum1NDk3MiT7Qf3xo",
+            encode_bytes(&derive_key(&shared_secret, b"auth-token", 32).unwrap())
+        );
+        assert_eq!(
+            "Us9qQwAXPmuYL12sokGyV7-rkTbxgm1wwq9E9TyxqOOfaICmIgyAej4ZT2H_9fTbOibftCotw7BXok0N1shj5Q",
+            encode_bytes(&derive_key(&shared_secret, b"channel-encryption", 64).unwrap())
+        );
+        assert_eq!(
+            "j1opef4WTToBcgIybGGXrg",
+            encode_bytes(&derive_key(&shared_secret, b"", 16).unwrap())
+        );
+        // The 32-byte shared secret of the P-256 suite
+        assert_eq!(
+            "jw1zfPSO0O3uQNBLUf3R4hMgZsu9N4hpFeRgYs0cs6k",
+            encode_bytes(&derive_key(&shared_secret[..32], b"auth-token", 32).unwrap())
+        );
+    }
+
+    #[test]
+    fn session_tag_vectors() {
+        let shared_secret: Vec<u8> = (0..64).collect();
+
+        assert_eq!(
+            "PonaeDioKWA1WA7e0I2RIQ",
+            encode_bytes(&session_tag(&shared_secret))
+        );
+        assert_eq!(
+            "Mw0CgOtGIpGOzYDNku0-bw",
+            encode_bytes(&session_tag(&shared_secret[..32]))
+        );
+    }
+
+    #[test]
+    fn verify() {
+        let shared_secret: Vec<u8> = (0..64).collect();
+        let mut other = shared_secret.clone();
+        other[63] ^= 1;
+
+        assert!(verify_session_key(&shared_secret, &shared_secret.clone()));
+        assert!(!verify_session_key(&shared_secret, &other));
+        assert!(!verify_session_key(&shared_secret, &shared_secret[..32]));
+
+        let tag = session_tag(&shared_secret);
+        assert!(verify_session_tag(&shared_secret, &tag));
+        assert!(!verify_session_tag(&other, &tag));
+        assert!(!verify_session_tag(&shared_secret, &tag[..8]));
+    }
+
+    #[test]
+    fn derive_key_len() {
+        let shared_secret = [7u8; 64];
+
+        assert_eq!(
+            MAX_DERIVED_KEY_LEN,
+            derive_key(&shared_secret, b"label", MAX_DERIVED_KEY_LEN)
+                .unwrap()
+                .len()
+        );
+        assert!(matches!(
+            derive_key(&shared_secret, b"label", MAX_DERIVED_KEY_LEN + 1),
+            Err(Error::InvalidKeyLength(_))
+        ));
+    }
+}
@@ -0,0 +1,405 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use opaque_ke::ciphersuite::CipherSuite;
+use opaque_ke::errors::ProtocolError;
+use opaque_ke::key_exchange::tripledh::TripleDh;
+use opaque_ke::{
+    ClientLogin, ClientLoginFinishParameters, ClientLoginFinishResult, ClientRegistration,
+    CredentialRequest, CredentialResponse, RegistrationResponse, RegistrationUpload, ServerLogin,
+    ServerLoginStartParameters, ServerLoginStartResult, ServerRegistration, ServerSetup,
+};
+use rand::{CryptoRng, RngCore};
+
+use crate::encoded::{decode_envelope, is_envelope};
+use crate::Error;
+
+/// Length of the suite tag that prefixes serialized setups, password files and states.
+pub const SUITE_TAG_LEN: usize = 1;
+
+/// Set on the suite tag of password files that record the key id of the setup they were created
+/// with, in which case the tag is followed by the key id as a big-endian u32.
+pub(crate) const KEY_ID_FLAG: u8 = 0x80;
+
+/// Runtime identifier of a [`Suite`]. Its byte value is the tag used in serialized setups,
+/// password files and states.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum SuiteId {
+    #[default]
+    Ristretto255Argon2 = 1,
+    P256Argon2 = 2,
+    P384Argon2 = 3,
+}
+
+impl SuiteId {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SuiteId::Ristretto255Argon2 => "ristretto255_argon2",
+            SuiteId::P256Argon2 => "p256_argon2",
+            SuiteId::P384Argon2 => "p384_argon2",
+        }
+    }
+
+    /// Reads the suite tag of a serialized setup, password file or state. Untagged input of
+    /// exactly `legacy_len` bytes (e.g. `SERVER_SETUP_LEN` for a setup) predates suite tags and
+    /// always belongs to the default suite, even if it starts with the envelope magic by chance.
+    /// Other envelopes are recognized by their header.
+    pub fn from_serialized(bytes: &[u8], legacy_len: usize) -> Result<Self, Error> {
+        if bytes.len() == legacy_len {
+            return Ok(SuiteId::Ristretto255Argon2);
+        }
+        if is_envelope(bytes) {
+            return Ok(decode_envelope(bytes)?.suite);
+        }
+        let tag = bytes.first().ok_or(ProtocolError::SerializationError)?;
+
+        SuiteId::try_from(*tag & !KEY_ID_FLAG)
+    }
+}
+
+impl TryFrom<u8> for SuiteId {
+    type Error = Error;
+
+    fn try_from(tag: u8) -> Result<Self, Self::Error> {
+        match tag {
+            1 => Ok(SuiteId::Ristretto255Argon2),
+            2 => Ok(SuiteId::P256Argon2),
+            3 => Ok(SuiteId::P384Argon2),
+            _ => Err(Error::UnknownSuite(format!("{:#04x}", tag))),
+        }
+    }
+}
+
+impl FromStr for SuiteId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            SuiteId::Ristretto255Argon2,
+            SuiteId::P256Argon2,
+            SuiteId::P384Argon2,
+        ]
+        .into_iter()
+        .find(|suite| suite.name() == s)
+        .ok_or_else(|| Error::UnknownSuite(s.to_owned()))
+    }
+}
+
+impl Display for SuiteId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A `CipherSuite` that can be selected at runtime using its [`SuiteId`]. All suites use Argon2
+/// as the key stretching function, configured using `KsfParams`.
+pub trait Suite: CipherSuite<Ksf = argon2::Argon2<'static>> + SuiteOps {
+    const ID: SuiteId;
+}
+
+/// The opaque-ke functions that require bounds on the lengths of the suite's types, which are
+/// only known for the concrete suites. This lets the generic code call them on any [`Suite`].
+#[doc(hidden)]
+pub trait SuiteOps: CipherSuite + Sized {
+    fn serialize_setup(setup: &ServerSetup<Self>) -> Vec<u8>;
+    fn serialize_server_registration(registration: &ServerRegistration<Self>) -> Vec<u8>;
+    fn serialize_client_registration(registration: &ClientRegistration<Self>) -> Vec<u8>;
+    fn serialize_client_login(login: &ClientLogin<Self>) -> Vec<u8>;
+    fn serialize_registration_response(message: &RegistrationResponse<Self>) -> Vec<u8>;
+    fn serialize_registration_upload(message: &RegistrationUpload<Self>) -> Vec<u8>;
+    fn serialize_credential_request(message: &CredentialRequest<Self>) -> Vec<u8>;
+    fn serialize_credential_response(message: &CredentialResponse<Self>) -> Vec<u8>;
+
+    fn server_login_start<R: CryptoRng + RngCore>(
+        rng: &mut R,
+        setup: &ServerSetup<Self>,
+        password_file: Option<ServerRegistration<Self>>,
+        request: CredentialRequest<Self>,
+        user_id: &[u8],
+        params: ServerLoginStartParameters,
+    ) -> Result<ServerLoginStartResult<Self>, ProtocolError>;
+
+    fn client_login_finish(
+        login: ClientLogin<Self>,
+        password: &[u8],
+        response: CredentialResponse<Self>,
+        params: ClientLoginFinishParameters<Self>,
+    ) -> Result<ClientLoginFinishResult<Self>, ProtocolError>;
+}
+
+macro_rules! impl_suite_ops {
+    ($suite:ty) => {
+        impl SuiteOps for $suite {
+            fn serialize_setup(setup: &ServerSetup<Self>) -> Vec<u8> {
+                setup.serialize().to_vec()
+            }
+
+            fn serialize_server_registration(registration: &ServerRegistration<Self>) -> Vec<u8> {
+                registration.serialize().to_vec()
+            }
+
+            fn serialize_client_registration(registration: &ClientRegistration<Self>) -> Vec<u8> {
+                registration.serialize().to_vec()
+            }
+
+            fn serialize_client_login(login: &ClientLogin<Self>) -> Vec<u8> {
+                login.serialize().to_vec()
+            }
+
+            fn serialize_registration_response(message: &RegistrationResponse<Self>) -> Vec<u8> {
+                message.serialize().to_vec()
+            }
+
+            fn serialize_registration_upload(message: &RegistrationUpload<Self>) -> Vec<u8> {
+                message.serialize().to_vec()
+            }
+
+            fn serialize_credential_request(message: &CredentialRequest<Self>) -> Vec<u8> {
+                message.serialize().to_vec()
+            }
+
+            fn serialize_credential_response(message: &CredentialResponse<Self>) -> Vec<u8> {
+                message.serialize().to_vec()
+            }
+
+            fn server_login_start<R: CryptoRng + RngCore>(
+                rng: &mut R,
+                setup: &ServerSetup<Self>,
+                password_file: Option<ServerRegistration<Self>>,
+                request: CredentialRequest<Self>,
+                user_id: &[u8],
+                params: ServerLoginStartParameters,
+            ) -> Result<ServerLoginStartResult<Self>, ProtocolError> {
+                ServerLogin::start(rng, setup, password_file, request, user_id, params)
+            }
+
+            fn client_login_finish(
+                login: ClientLogin<Self>,
+                password: &[u8],
+                response: CredentialResponse<Self>,
+                params: ClientLoginFinishParameters<Self>,
+            ) -> Result<ClientLoginFinishResult<Self>, ProtocolError> {
+                login.finish(password, response, params)
+            }
+        }
+    };
+}
+
+/// Ristretto255 for both the OPRF and the key exchange, with SHA-512. This is the default suite
+/// and the only one available before suites were introduced.
+pub struct Ristretto255Argon2;
+impl CipherSuite for Ristretto255Argon2 {
+    type OprfCs = opaque_ke::Ristretto255;
+    type KeGroup = opaque_ke::Ristretto255;
+    type KeyExchange = TripleDh;
+    type Ksf = argon2::Argon2<'static>;
+}
+impl Suite for Ristretto255Argon2 {
+    const ID: SuiteId = SuiteId::Ristretto255Argon2;
+}
+impl_suite_ops!(Ristretto255Argon2);
+
+/// NIST P-256 for both the OPRF and the key exchange, with SHA-256.
+pub struct P256Argon2;
+impl CipherSuite for P256Argon2 {
+    type OprfCs = p256::NistP256;
+    type KeGroup = p256::NistP256;
+    type KeyExchange = TripleDh;
+    type Ksf = argon2::Argon2<'static>;
+}
+impl Suite for P256Argon2 {
+    const ID: SuiteId = SuiteId::P256Argon2;
+}
+impl_suite_ops!(P256Argon2);
+
+/// NIST P-384 for both the OPRF and the key exchange, with SHA-384.
+pub struct P384Argon2;
+impl CipherSuite for P384Argon2 {
+    type OprfCs = p384::NistP384;
+    type KeGroup = p384::NistP384;
+    type KeyExchange = TripleDh;
+    type Ksf = argon2::Argon2<'static>;
+}
+impl Suite for P384Argon2 {
+    const ID: SuiteId = SuiteId::P384Argon2;
+}
+impl_suite_ops!(P384Argon2);
+
+/// Runs `$body` with `$cs` defined as the suite type belonging to the `SuiteId` `$suite`, for
+/// calling the generic functions when the suite is only known at runtime.
+///
+/// ```
+/// use opaque_borink::server::ServerSetup;
+/// use opaque_borink::suite::SuiteId;
+///
+/// let suite: SuiteId = "p256_argon2".parse().unwrap();
+/// let setup = opaque_borink::with_suite!(suite, CS => ServerSetup::<CS>::create().serialize());
+/// ```
+#[macro_export]
+macro_rules! with_suite {
+    ($suite:expr, $cs:ident => $body:expr) => {
+        match $suite {
+            $crate::suite::SuiteId::Ristretto255Argon2 => {
+                type $cs = $crate::suite::Ristretto255Argon2;
+                $body
+            }
+            $crate::suite::SuiteId::P256Argon2 => {
+                type $cs = $crate::suite::P256Argon2;
+                $body
+            }
+            $crate::suite::SuiteId::P384Argon2 => {
+                type $cs = $crate::suite::P384Argon2;
+                $body
+            }
+        }
+    };
+}
+
+/// Prefixes the concatenation of `parts` with the tag of `CS`.
+pub(crate) fn tag<CS: Suite>(parts: &[&[u8]]) -> Vec<u8> {
+    let mut bytes =
+        Vec::with_capacity(SUITE_TAG_LEN + parts.iter().map(|p| p.len()).sum::<usize>());
+    bytes.push(CS::ID as u8);
+    for part in parts {
+        bytes.extend_from_slice(part);
+    }
+
+    bytes
+}
+
+/// Strips the suite tag, checking that it belongs to `CS`. Untagged input of exactly `legacy_len`
+/// bytes is accepted for the default suite, in which case the second element is `true`.
+pub(crate) fn untag<CS: Suite>(bytes: &[u8], legacy_len: usize) -> Result<(&[u8], bool), Error> {
+    if CS::ID == SuiteId::Ristretto255Argon2 && bytes.len() == legacy_len {
+        return Ok((bytes, true));
+    }
+    let (tag, rest) = bytes
+        .split_first()
+        .ok_or(ProtocolError::SerializationError)?;
+    check_tag::<CS>(*tag)?;
+
+    Ok((rest, false))
+}
+
+/// Like [`untag`], but also reads the key id following a tag with the [`KEY_ID_FLAG`] set.
+pub(crate) fn untag_key_id<CS: Suite>(
+    bytes: &[u8],
+    legacy_len: usize,
+) -> Result<(&[u8], bool, Option<u32>), Error> {
+    // Untagged input can start with any byte, so it is recognized first
+    if CS::ID == SuiteId::Ristretto255Argon2 && bytes.len() == legacy_len {
+        return Ok((bytes, true, None));
+    }
+    match bytes.first() {
+        Some(tag) if tag & KEY_ID_FLAG != 0 => {
+            check_tag::<CS>(tag & !KEY_ID_FLAG)?;
+            if bytes.len() < SUITE_TAG_LEN + 4 {
+                return Err(ProtocolError::SerializationError.into());
+            }
+            let key_id = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+
+            Ok((&bytes[SUITE_TAG_LEN + 4..], false, Some(key_id)))
+        }
+        _ => {
+            let (rest, legacy) = untag::<CS>(bytes, legacy_len)?;
+
+            Ok((rest, legacy, None))
+        }
+    }
+}
+
+fn check_tag<CS: Suite>(tag: u8) -> Result<(), Error> {
+    let found = SuiteId::try_from(tag)?;
+    if found != CS::ID {
+        return Err(Error::SuiteMismatch {
+            expected: CS::ID,
+            found,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoded::{encode_envelope, ObjectKind};
+
+    use super::*;
+
+    #[test]
+    fn names_roundtrip() {
+        for suite in [
+            SuiteId::Ristretto255Argon2,
+            SuiteId::P256Argon2,
+            SuiteId::P384Argon2,
+        ] {
+            assert_eq!(suite, suite.name().parse().unwrap());
+            assert_eq!(suite, SuiteId::try_from(suite as u8).unwrap());
+        }
+        assert!("p521_argon2".parse::<SuiteId>().is_err());
+        assert!(SuiteId::try_from(0u8).is_err());
+    }
+
+    #[test]
+    fn from_serialized() {
+        assert_eq!(
+            SuiteId::P256Argon2,
+            SuiteId::from_serialized(&[2, 7, 7], 2).unwrap()
+        );
+        assert_eq!(
+            SuiteId::Ristretto255Argon2,
+            SuiteId::from_serialized(&[2, 7], 2).unwrap()
+        );
+
+        // Untagged input can start with what looks like an envelope header
+        let envelope = encode_envelope(ObjectKind::ServerSetup, SuiteId::P384Argon2, &[7; 4]);
+        assert_eq!(
+            SuiteId::P384Argon2,
+            SuiteId::from_serialized(&envelope, 4).unwrap()
+        );
+        assert_eq!(
+            SuiteId::Ristretto255Argon2,
+            SuiteId::from_serialized(&envelope, envelope.len()).unwrap()
+        );
+    }
+
+    #[test]
+    fn tag_mismatch() {
+        let tagged = tag::<P256Argon2>(&[&[7, 7]]);
+
+        assert_eq!(
+            untag::<P256Argon2>(&tagged, 2).unwrap(),
+            (&[7u8, 7][..], false)
+        );
+        // Lengths decide whether input predates the suite tags
+        assert!(matches!(
+            untag::<Ristretto255Argon2>(&tagged, 3),
+            Ok((_, true))
+        ));
+        assert!(matches!(
+            untag::<Ristretto255Argon2>(&tagged, 4),
+            Err(Error::SuiteMismatch {
+                expected: SuiteId::Ristretto255Argon2,
+                found: SuiteId::P256Argon2
+            })
+        ));
+    }
+
+    #[test]
+    fn key_id_tag() {
+        let mut tagged = tag::<P256Argon2>(&[&7u32.to_be_bytes(), &[1, 2]]);
+        tagged[0] |= KEY_ID_FLAG;
+
+        assert_eq!(
+            SuiteId::P256Argon2,
+            SuiteId::from_serialized(&tagged, 0).unwrap()
+        );
+        assert_eq!(
+            untag_key_id::<P256Argon2>(&tagged, 2).unwrap(),
+            (&[1u8, 2][..], false, Some(7))
+        );
+        assert!(untag_key_id::<P384Argon2>(&tagged, 2).is_err());
+        assert!(untag_key_id::<P256Argon2>(&tagged[..3], 2).is_err());
+    }
+}
@@ -0,0 +1,477 @@
+//! The protocol functions with a distinct type for every message and for the server login state,
+//! so that e.g. a registration message can't be passed to a login function. The wrappers hold the
+//! same bytes as the results of the functions taking `&[u8]`, which remain available and can be
+//! mixed with these.
+
+use crate::encoded::{
+    decode_envelope, decode_secret, decode_string, encode_bytes, is_envelope, ObjectKind,
+};
+use crate::ksf::KsfParams;
+use crate::opaque_impl::{
+    self, ClientChangePasswordFinishResult, ClientChangePasswordStartResult,
+    ClientLoginFinishResult, ClientLoginStartResult, ClientRegistrationFinishResult,
+    ClientRegistrationStartResult, ClientStateChangePassword, ClientStateLogin,
+    ClientStateRegistration, PasswordFile, ServerChangePasswordFinishResult,
+    ServerLoginFinishResult, ServerLoginStartResult, ServerRegistrationStartResult,
+    ServerSetupView, LOGIN_CLIENT_MESSAGE_LEN, LOGIN_FINISH_MESSAGE_LEN, LOGIN_SERVER_MESSAGE_LEN,
+    LOGIN_SERVER_STATE_LEN, REGISTER_CLIENT_MESSAGE_LEN, REGISTER_FINISH_MESSAGE_LEN,
+    REGISTER_SERVER_MESSAGE_LEN, REGISTER_UPLOAD_LEN,
+};
+use crate::replay::StateChecks;
+use crate::secret::SecretBytes;
+use crate::suite::Suite;
+use crate::{Error, Identifiers};
+
+macro_rules! message_bytes {
+    ($(#[$attr:meta])* $name:ident => $kind:ident, $legacy_lens:expr) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        pub struct $name(Vec<u8>);
+
+        impl $name {
+            /// The kind of envelope this wraps, when it is in an envelope.
+            pub const KIND: ObjectKind = ObjectKind::$kind;
+            const LEGACY_LENS: &'static [usize] = &$legacy_lens;
+
+            /// Fails with `Error::WrongObjectKind` if `bytes` is an envelope of another kind. Other
+            /// checks happen when it is deserialized.
+            pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+                check_kind(bytes, Self::KIND, Self::LEGACY_LENS)?;
+
+                Ok(Self(bytes.to_vec()))
+            }
+
+            /// Decodes base64url without padding, as produced by [`Self::to_base64`].
+            pub fn from_base64(encoded: &str) -> Result<Self, Error> {
+                let bytes = decode_string(encoded)?;
+                check_kind(&bytes, Self::KIND, Self::LEGACY_LENS)?;
+
+                Ok(Self(bytes))
+            }
+
+            pub fn to_base64(&self) -> String {
+                encode_bytes(&self.0)
+            }
+
+            pub fn as_bytes(&self) -> &[u8] {
+                &self.0
+            }
+
+            pub fn into_bytes(self) -> Vec<u8> {
+                self.0
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+    };
+}
+
+message_bytes!(
+    /// Sent by the client to start the registration.
+    RegistrationRequestBytes => RegistrationRequest, [REGISTER_CLIENT_MESSAGE_LEN]
+);
+message_bytes!(
+    /// Sent by the server in response to a [`RegistrationRequestBytes`].
+    RegistrationResponseBytes => RegistrationResponse, [REGISTER_SERVER_MESSAGE_LEN]
+);
+message_bytes!(
+    /// Sent by the client to finish the registration, from which the server creates the password
+    /// file.
+    RegistrationUploadBytes => RegistrationUpload, [REGISTER_UPLOAD_LEN, REGISTER_FINISH_MESSAGE_LEN]
+);
+message_bytes!(
+    /// Sent by the client to start the login.
+    LoginStartRequest => CredentialRequest, [LOGIN_CLIENT_MESSAGE_LEN]
+);
+message_bytes!(
+    /// Sent by the server in response to a [`LoginStartRequest`].
+    LoginStartResponse => CredentialResponse, [LOGIN_SERVER_MESSAGE_LEN]
+);
+message_bytes!(
+    /// Sent by the client to finish the login.
+    LoginFinishRequest => CredentialFinalization, [LOGIN_FINISH_MESSAGE_LEN]
+);
+/// Kept by the server between starting and finishing a login or password change. Unlike the
+/// messages it is secret, so it is zeroized on drop and does not implement `Debug`.
+#[derive(Clone)]
+pub struct LoginServerState(SecretBytes);
+
+impl LoginServerState {
+    pub const KIND: ObjectKind = ObjectKind::ServerLoginState;
+    const LEGACY_LENS: &'static [usize] = &[LOGIN_SERVER_STATE_LEN];
+
+    /// Fails with `Error::WrongObjectKind` if `bytes` is an envelope of another kind.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        check_kind(bytes, Self::KIND, Self::LEGACY_LENS)?;
+
+        Ok(Self(bytes.into()))
+    }
+
+    pub fn from_base64(encoded: &str) -> Result<Self, Error> {
+        let bytes = decode_secret(encoded)?;
+        check_kind(&bytes, Self::KIND, Self::LEGACY_LENS)?;
+
+        Ok(Self(bytes))
+    }
+
+    pub fn to_base64(&self) -> String {
+        encode_bytes(&self.0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> SecretBytes {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for LoginServerState {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+message_bytes!(
+    /// Sent by the client to start a password change.
+    ChangePasswordStartRequest => ChangePasswordRequest, []
+);
+message_bytes!(
+    /// Sent by the server in response to a [`ChangePasswordStartRequest`].
+    ChangePasswordStartResponse => ChangePasswordResponse, []
+);
+message_bytes!(
+    /// Sent by the client to finish a password change.
+    ChangePasswordFinishRequest => ChangePasswordFinalization, []
+);
+
+/// `legacy_lens` are the lengths of the bare forms of the default suite, which can start with the
+/// envelope magic by chance, like in `open_envelope`.
+fn check_kind(bytes: &[u8], kind: ObjectKind, legacy_lens: &[usize]) -> Result<(), Error> {
+    if is_envelope(bytes) && !legacy_lens.contains(&bytes.len()) {
+        let found = decode_envelope(bytes)?.kind;
+        if found != kind {
+            return Err(Error::WrongObjectKind {
+                expected: kind,
+                found,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// See `client::client_register_start`.
+pub fn client_register_start<CS: Suite>(
+    client_state: &mut ClientStateRegistration<CS>,
+    password: &[u8],
+    ksf_params: KsfParams,
+) -> Result<ClientRegistrationStartResult<RegistrationRequestBytes>, Error> {
+    let result = opaque_impl::client_register_start(client_state, password, ksf_params)?;
+
+    Ok(ClientRegistrationStartResult {
+        response: RegistrationRequestBytes(result.response),
+    })
+}
+
+/// See `server::server_register_start`.
+pub fn server_register_start<CS: Suite>(
+    server_setup: &mut ServerSetupView<CS>,
+    register_start_request: &RegistrationRequestBytes,
+    user_id: &[u8],
+) -> Result<ServerRegistrationStartResult<RegistrationResponseBytes>, Error> {
+    let result = opaque_impl::server_register_start(
+        server_setup,
+        register_start_request.as_bytes(),
+        user_id,
+    )?;
+
+    Ok(ServerRegistrationStartResult {
+        response: RegistrationResponseBytes(result.response),
+    })
+}
+
+/// See `client::client_register_finish`.
+pub fn client_register_finish<CS: Suite>(
+    client_state: &mut ClientStateRegistration<CS>,
+    password: &[u8],
+    server_message: &RegistrationResponseBytes,
+    identifiers: Identifiers,
+) -> Result<ClientRegistrationFinishResult<RegistrationUploadBytes>, Error> {
+    let result = opaque_impl::client_register_finish(
+        client_state,
+        password,
+        server_message.as_bytes(),
+        identifiers,
+    )?;
+
+    Ok(ClientRegistrationFinishResult {
+        response: RegistrationUploadBytes(result.response),
+        export_key: result.export_key,
+        server_public_key: result.server_public_key,
+    })
+}
+
+/// See `server::server_register_finish`.
+pub fn server_register_finish<CS: Suite>(
+    register_finish_request: &RegistrationUploadBytes,
+) -> Result<PasswordFile<CS>, Error> {
+    opaque_impl::server_register_finish(register_finish_request.as_bytes())
+}
+
+/// See `client::client_login_start`.
+pub fn client_login_start<CS: Suite>(
+    client_state: &mut ClientStateLogin<CS>,
+    password: &[u8],
+) -> Result<ClientLoginStartResult<LoginStartRequest>, Error> {
+    let result = opaque_impl::client_login_start(client_state, password)?;
+
+    Ok(ClientLoginStartResult {
+        response: LoginStartRequest(result.response),
+    })
+}
+
+/// See `server::server_login_start`.
+pub fn server_login_start<CS: Suite>(
+    setup: &mut ServerSetupView<CS>,
+    password_file: Option<&PasswordFile<CS>>,
+    login_start_request: &LoginStartRequest,
+    user_id: &str,
+    identifiers: Identifiers,
+    context: Option<&[u8]>,
+) -> Result<ServerLoginStartResult<LoginStartResponse, LoginServerState>, Error> {
+    let result = opaque_impl::server_login_start(
+        setup,
+        password_file,
+        login_start_request.as_bytes(),
+        user_id,
+        identifiers,
+        context,
+    )?;
+
+    Ok(ServerLoginStartResult {
+        response: LoginStartResponse(result.response),
+        state: LoginServerState(result.state),
+    })
+}
+
+/// See `client::client_login_finish`.
+pub fn client_login_finish<CS: Suite>(
+    client_state: &mut ClientStateLogin<CS>,
+    password: &[u8],
+    server_message: &LoginStartResponse,
+    ksf_params: KsfParams,
+    identifiers: Identifiers,
+    context: Option<&[u8]>,
+    expected_server_public_key: Option<&[u8]>,
+) -> Result<ClientLoginFinishResult<LoginFinishRequest>, Error> {
+    let result = opaque_impl::client_login_finish(
+        client_state,
+        password,
+        server_message.as_bytes(),
+        ksf_params,
+        identifiers,
+        context,
+        expected_server_public_key,
+    )?;
+
+    Ok(ClientLoginFinishResult {
+        response: LoginFinishRequest(result.response),
+        shared_secret: result.shared_secret,
+        export_key: result.export_key,
+        server_public_key: result.server_public_key,
+    })
+}
+
+/// See `server::server_login_finish`.
+pub fn server_login_finish<CS: Suite>(
+    login_finish_request: &LoginFinishRequest,
+    server_state: &LoginServerState,
+    checks: &StateChecks,
+) -> Result<ServerLoginFinishResult, Error> {
+    opaque_impl::server_login_finish::<CS>(
+        login_finish_request.as_bytes(),
+        server_state.as_bytes(),
+        checks,
+    )
+}
+
+/// See `client::client_change_password_start`.
+pub fn client_change_password_start<CS: Suite>(
+    client_state: &mut ClientStateChangePassword<CS>,
+    old_password: &[u8],
+    new_password: &[u8],
+    ksf_params: KsfParams,
+) -> Result<ClientChangePasswordStartResult<ChangePasswordStartRequest>, Error> {
+    let result = opaque_impl::client_change_password_start(
+        client_state,
+        old_password,
+        new_password,
+        ksf_params,
+    )?;
+
+    Ok(ClientChangePasswordStartResult {
+        response: ChangePasswordStartRequest(result.response),
+    })
+}
+
+/// See `server::server_change_password_start`.
+pub fn server_change_password_start<CS: Suite>(
+    setup: &mut ServerSetupView<CS>,
+    password_file: &PasswordFile<CS>,
+    change_password_request: &ChangePasswordStartRequest,
+    user_id: &str,
+    identifiers: Identifiers,
+    context: Option<&[u8]>,
+) -> Result<ServerLoginStartResult<ChangePasswordStartResponse, LoginServerState>, Error> {
+    let result = opaque_impl::server_change_password_start(
+        setup,
+        password_file,
+        change_password_request.as_bytes(),
+        user_id,
+        identifiers,
+        context,
+    )?;
+
+    Ok(ServerLoginStartResult {
+        response: ChangePasswordStartResponse(result.response),
+        state: LoginServerState(result.state),
+    })
+}
+
+/// See `client::client_change_password_finish`.
+#[allow(clippy::too_many_arguments)]
+pub fn client_change_password_finish<CS: Suite>(
+    client_state: &mut ClientStateChangePassword<CS>,
+    old_password: &[u8],
+    new_password: &[u8],
+    server_message: &ChangePasswordStartResponse,
+    ksf_params: KsfParams,
+    identifiers: Identifiers,
+    context: Option<&[u8]>,
+    expected_server_public_key: Option<&[u8]>,
+) -> Result<ClientChangePasswordFinishResult<ChangePasswordFinishRequest>, Error> {
+    let result = opaque_impl::client_change_password_finish(
+        client_state,
+        old_password,
+        new_password,
+        server_message.as_bytes(),
+        ksf_params,
+        identifiers,
+        context,
+        expected_server_public_key,
+    )?;
+
+    Ok(ClientChangePasswordFinishResult {
+        response: ChangePasswordFinishRequest(result.response),
+        shared_secret: result.shared_secret,
+        export_key: result.export_key,
+        old_export_key: result.old_export_key,
+    })
+}
+
+/// See `server::server_change_password_finish`.
+pub fn server_change_password_finish<CS: Suite>(
+    change_password_finish_request: &ChangePasswordFinishRequest,
+    server_state: &LoginServerState,
+    checks: &StateChecks,
+) -> Result<ServerChangePasswordFinishResult<CS>, Error> {
+    opaque_impl::server_change_password_finish(
+        change_password_finish_request.as_bytes(),
+        server_state.as_bytes(),
+        checks,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoded::encode_envelope;
+    use crate::server::ServerSetup;
+    use crate::suite::SuiteId;
+    use crate::Cipher;
+
+    use super::*;
+
+    #[test]
+    fn typed_login() {
+        let setup = ServerSetup::<Cipher>::create();
+        let mut client_state = ClientStateRegistration::<Cipher>::setup();
+        let client_start =
+            client_register_start(&mut client_state, b"my_pass", KsfParams::default()).unwrap();
+        let request =
+            RegistrationRequestBytes::from_base64(&client_start.response.to_base64()).unwrap();
+        let server_start = server_register_start(&mut setup.view(), &request, b"my_user").unwrap();
+        let client_finish = client_register_finish(
+            &mut client_state,
+            b"my_pass",
+            &server_start.response,
+            Identifiers::default(),
+        )
+        .unwrap();
+        let password_file = server_register_finish::<Cipher>(&client_finish.response).unwrap();
+
+        let mut client_state = ClientStateLogin::<Cipher>::setup();
+        let client_start = client_login_start(&mut client_state, b"my_pass").unwrap();
+        let server_start = server_login_start(
+            &mut setup.view(),
+            Some(&password_file),
+            &client_start.response,
+            "my_user",
+            Identifiers::default(),
+            None,
+        )
+        .unwrap();
+        let client_finish = client_login_finish(
+            &mut client_state,
+            b"my_pass",
+            &server_start.response,
+            KsfParams::default(),
+            Identifiers::default(),
+            None,
+            None,
+        )
+        .unwrap();
+        // The byte functions accept the same messages
+        let server_finish = opaque_impl::server_login_finish::<Cipher>(
+            client_finish.response.as_bytes(),
+            server_start.state.as_bytes(),
+            &StateChecks::default(),
+        )
+        .unwrap();
+        assert_eq!(*client_finish.shared_secret, *server_finish.shared_secret);
+    }
+
+    #[test]
+    fn wrong_kind() {
+        let envelope = encode_envelope(
+            ObjectKind::RegistrationRequest,
+            SuiteId::Ristretto255Argon2,
+            &[1, 2, 3],
+        );
+
+        assert!(RegistrationRequestBytes::from_bytes(&envelope).is_ok());
+        assert!(matches!(
+            LoginStartRequest::from_bytes(&envelope),
+            Err(Error::WrongObjectKind {
+                expected: ObjectKind::CredentialRequest,
+                found: ObjectKind::RegistrationRequest
+            })
+        ));
+        assert!(LoginStartRequest::from_base64("not base64!").is_err());
+        assert_eq!(
+            &[1, 2, 3],
+            LoginStartRequest::from_bytes(&[1, 2, 3])
+                .unwrap()
+                .as_bytes()
+        );
+
+        // A bare message can start with what looks like an envelope header
+        let mut bare = [7u8; LOGIN_FINISH_MESSAGE_LEN];
+        bare[..envelope.len()].copy_from_slice(&envelope);
+        assert!(LoginFinishRequest::from_bytes(&bare).is_ok());
+    }
+}
@@ -0,0 +1,195 @@
+//! Test vectors: the bytes of a registration and login of every suite, using seeded random number
+//! generators. They are stored in `test-vectors.json`, against which this crate, opaquepy and
+//! opaquewasm test their outputs. Regenerate the file using
+//! `cargo run --example test_vectors --features test-vectors > test-vectors.json`.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+use crate::encoded::encode_bytes;
+use crate::ksf::KsfParams;
+use crate::opaque_impl::{
+    client_login_finish, client_login_start, client_register_finish, client_register_start,
+    server_login_finish, server_login_start, server_register_finish, server_register_start,
+    ClientStateLogin, ClientStateRegistration, ServerSetup,
+};
+use crate::replay::StateChecks;
+use crate::suite::Suite;
+use crate::Identifiers;
+
+pub const PASSWORD: &str = "clientele";
+pub const CREDENTIAL_ID: &str = "someperson";
+
+/// Creation time of the `login_server_state`, 2026-01-01T00:00:00Z.
+pub const CREATED_AT: u64 = 1_767_225_600;
+
+/// The names and base64url-encoded values of the vector of `CS`, in protocol order.
+pub fn generate<CS: Suite>() -> Vec<(&'static str, String)> {
+    let password = PASSWORD.as_bytes();
+    let ksf = KsfParams::default();
+    let setup = ServerSetup::<CS>::create_with_rng(&mut ChaCha20Rng::from_seed([1; 32]));
+
+    let mut register_state =
+        ClientStateRegistration::<CS>::with_rng(ChaCha20Rng::from_seed([2; 32]));
+    let register_start = client_register_start(&mut register_state, password, ksf).unwrap();
+    let register_response = server_register_start(
+        &mut setup.view(),
+        &register_start.response,
+        CREDENTIAL_ID.as_bytes(),
+    )
+    .unwrap();
+    let register_finish = client_register_finish(
+        &mut register_state,
+        password,
+        &register_response.response,
+        Identifiers::default(),
+    )
+    .unwrap();
+    let password_file = server_register_finish::<CS>(&register_finish.response).unwrap();
+
+    let mut login_state = ClientStateLogin::<CS>::with_rng(ChaCha20Rng::from_seed([3; 32]));
+    let login_start = client_login_start(&mut login_state, password).unwrap();
+    let login_client_state = login_state.serialize().unwrap();
+    let login_response = server_login_start(
+        &mut setup
+            .view_with_rng(ChaCha20Rng::from_seed([4; 32]))
+            .at_time(CREATED_AT),
+        Some(&password_file),
+        &login_start.response,
+        CREDENTIAL_ID,
+        Identifiers::default(),
+        None,
+    )
+    .unwrap();
+    let login_finish = client_login_finish(
+        &mut login_state,
+        password,
+        &login_response.response,
+        ksf,
+        Identifiers::default(),
+        None,
+        None,
+    )
+    .unwrap();
+    let server_finish = server_login_finish::<CS>(
+        &login_finish.response,
+        &login_response.state,
+        &StateChecks::default(),
+    )
+    .unwrap();
+
+    vec![
+        ("suite", CS::ID.name().to_owned()),
+        ("password", PASSWORD.to_owned()),
+        ("credential_id", CREDENTIAL_ID.to_owned()),
+        ("ksf_params", encode_bytes(&ksf.serialize())),
+        ("setup", encode_bytes(&setup.serialize())),
+        ("server_public_key", encode_bytes(&setup.public_key())),
+        ("register_request", encode_bytes(&register_start.response)),
+        (
+            "register_response",
+            encode_bytes(&register_response.response),
+        ),
+        ("register_upload", encode_bytes(&register_finish.response)),
+        ("password_file", encode_bytes(&password_file.serialize())),
+        ("login_request", encode_bytes(&login_start.response)),
+        ("login_client_state", encode_bytes(&login_client_state)),
+        ("login_response", encode_bytes(&login_response.response)),
+        ("login_server_state", encode_bytes(&login_response.state)),
+        ("login_finish", encode_bytes(&login_finish.response)),
+        ("export_key", encode_bytes(&login_finish.export_key)),
+        ("session_key", encode_bytes(&server_finish.shared_secret)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::encoded::decode_string;
+    use crate::opaque_impl::PasswordFile;
+    use crate::suite::SuiteId;
+    use crate::with_suite;
+
+    use super::*;
+
+    fn vectors() -> Vec<Value> {
+        let file: Value = serde_json::from_str(include_str!("../test-vectors.json")).unwrap();
+
+        file["vectors"].as_array().unwrap().clone()
+    }
+
+    fn bytes(vector: &Value, name: &str) -> Vec<u8> {
+        decode_string(vector[name].as_str().unwrap()).unwrap()
+    }
+
+    /// Seeded generation still reproduces the file.
+    #[test]
+    fn generated() {
+        let vectors = vectors();
+        assert_eq!(3, vectors.len());
+
+        for vector in vectors {
+            let suite: SuiteId = vector["suite"].as_str().unwrap().parse().unwrap();
+            for (name, value) in with_suite!(suite, CS => generate::<CS>()) {
+                assert_eq!(vector[name].as_str().unwrap(), value, "{} {}", suite, name);
+            }
+        }
+    }
+
+    /// The deterministic steps give the recorded outputs when run on the recorded inputs.
+    #[test]
+    fn conformance() {
+        for vector in vectors() {
+            let suite: SuiteId = vector["suite"].as_str().unwrap().parse().unwrap();
+            with_suite!(suite, CS => check::<CS>(&vector));
+        }
+    }
+
+    fn check<CS: Suite>(vector: &Value) {
+        let password = vector["password"].as_str().unwrap().as_bytes();
+        let credential_id = vector["credential_id"].as_str().unwrap();
+        let ksf = KsfParams::deserialize(&bytes(vector, "ksf_params")).unwrap();
+        let setup = ServerSetup::<CS>::deserialize(&bytes(vector, "setup")).unwrap();
+        assert_eq!(bytes(vector, "server_public_key"), setup.public_key());
+
+        let register_response = server_register_start(
+            &mut setup.view(),
+            &bytes(vector, "register_request"),
+            credential_id.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            bytes(vector, "register_response"),
+            register_response.response
+        );
+        let password_file =
+            server_register_finish::<CS>(&bytes(vector, "register_upload")).unwrap();
+        assert_eq!(bytes(vector, "password_file"), password_file.serialize());
+        assert!(PasswordFile::<CS>::deserialize(&bytes(vector, "password_file")).is_ok());
+
+        let mut login_state =
+            ClientStateLogin::<CS>::deserialize(&bytes(vector, "login_client_state")).unwrap();
+        let login_finish = client_login_finish(
+            &mut login_state,
+            password,
+            &bytes(vector, "login_response"),
+            ksf,
+            Identifiers::default(),
+            None,
+            Some(&bytes(vector, "server_public_key")),
+        )
+        .unwrap();
+        assert_eq!(bytes(vector, "login_finish"), login_finish.response);
+        assert_eq!(bytes(vector, "export_key"), *login_finish.export_key);
+        assert_eq!(bytes(vector, "session_key"), *login_finish.shared_secret);
+
+        let server_finish = server_login_finish::<CS>(
+            &bytes(vector, "login_finish"),
+            &bytes(vector, "login_server_state"),
+            &StateChecks::default(),
+        )
+        .unwrap();
+        assert_eq!(bytes(vector, "session_key"), *server_finish.shared_secret);
+    }
+}
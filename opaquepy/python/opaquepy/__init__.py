@@ -1,3 +1,13 @@
+from .opaquepy import (
+    OpaqueError,
+    InvalidLogin,
+    DecodeError,
+    SerializationError,
+    WrongObjectKind,
+    InvalidState,
+    InvalidParameters,
+    InternalError,
+)
 from .lib import (
     create_setup,
     register,
@@ -6,6 +16,41 @@ from .lib import (
     login_finish,
     register_client,
     register_client_finish,
+    login_client,
+    login_client_finish,
+    create_ksf_params,
+    password_file_ksf_params,
+    setup_public_key,
+    setup_envelope,
+    password_file_envelope,
+    create_setup_ring,
+    setup_ring_from_setup,
+    rotate_setup_ring,
+    remove_setup_ring_key,
+    register_ring,
+    login_ring,
+    password_file_key_id,
+    change_password,
+    change_password_ring,
+    change_password_finish,
+    change_password_client,
+    change_password_client_finish,
+    derive_key,
+    session_tag,
+    verify_session_key,
+    verify_session_tag,
+    create_state_key,
+    seal_login_state,
+    login_finish_sealed,
+    register_async,
+    register_ring_async,
+    register_client_finish_async,
+    login_async,
+    login_ring_async,
+    login_client_finish_async,
+    change_password_async,
+    change_password_ring_async,
+    change_password_client_finish_async,
 )
 
 __all__ = [
@@ -16,4 +61,47 @@ __all__ = [
     "login_finish",
     "register_client",
     "register_client_finish",
+    "login_client",
+    "login_client_finish",
+    "create_ksf_params",
+    "password_file_ksf_params",
+    "setup_public_key",
+    "setup_envelope",
+    "password_file_envelope",
+    "create_setup_ring",
+    "setup_ring_from_setup",
+    "rotate_setup_ring",
+    "remove_setup_ring_key",
+    "register_ring",
+    "login_ring",
+    "password_file_key_id",
+    "change_password",
+    "change_password_ring",
+    "change_password_finish",
+    "change_password_client",
+    "change_password_client_finish",
+    "derive_key",
+    "session_tag",
+    "verify_session_key",
+    "verify_session_tag",
+    "create_state_key",
+    "seal_login_state",
+    "login_finish_sealed",
+    "register_async",
+    "register_ring_async",
+    "register_client_finish_async",
+    "login_async",
+    "login_ring_async",
+    "login_client_finish_async",
+    "change_password_async",
+    "change_password_ring_async",
+    "change_password_client_finish_async",
+    "OpaqueError",
+    "InvalidLogin",
+    "DecodeError",
+    "SerializationError",
+    "WrongObjectKind",
+    "InvalidState",
+    "InvalidParameters",
+    "InternalError",
 ]
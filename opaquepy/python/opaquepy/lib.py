@@ -1,13 +1,18 @@
+import asyncio
+from typing import Callable, Optional
+
 from .opaquepy import _internal
 
 
-def create_setup() -> str:
+def create_setup(suite: Optional[str] = None) -> str:
     """
-    Generate a server setup, which is used for validating password files.
+    Generate a server setup, which is used for validating password files. All other functions determine the cipher
+    suite from the setup, password file or state they are given.
 
+    :param suite: One of 'ristretto255_argon2' (the default), 'p256_argon2' or 'p384_argon2'.
     :return: Encoded server setup state.
     """
-    return _internal.create_setup_py()
+    return _internal.create_setup_py(suite)
 
 
 def register(setup: str, client_request: str, credential_id: str) -> str:
@@ -22,83 +27,275 @@ def register(setup: str, client_request: str, credential_id: str) -> str:
     return _internal.register_server_py(setup, client_request, credential_id)
 
 
-def register_finish(client_request_finish: str) -> str:
+def register_finish(client_request_finish: str, suite: Optional[str] = None, key_id: Optional[int] = None) -> str:
     """
     Finish the registration on the server. This generates the password file.
 
     :param client_request_finish:
+    :param suite: Cipher suite of the server setup, the default suite is used if not provided.
+    :param key_id: Key id of the setup in a setup ring used for the registration, as returned by `register_ring`.
+    It is recorded in the password file, so that `login_ring` can find the setup again.
     :return: Encoded password file to be saved.
     """
-    return _internal.register_server_finish_py(client_request_finish)
+    return _internal.register_server_finish_py(client_request_finish, suite, key_id)
+
+
+def create_setup_ring(key_id: int = 0, suite: Optional[str] = None) -> str:
+    """
+    Generate a setup ring, which holds several server setups under their key id. This allows rotating the setup
+    without invalidating the password files created with the previous ones.
+
+    :param key_id: Key id of the initial setup, which becomes the current one.
+    :param suite: Cipher suite of all setups in the ring.
+    :return: Encoded setup ring.
+    """
+    return _internal.create_setup_ring_py(key_id, suite)
+
+
+def setup_ring_from_setup(setup: str, key_id: int = 0) -> str:
+    """
+    Create a setup ring containing an existing setup. Password files registered before setup rings were used have no
+    key id and are assumed to belong to key id 0, so keep the default when migrating.
+
+    :param setup:
+    :param key_id:
+    :return: Encoded setup ring.
+    """
+    return _internal.setup_ring_from_setup_py(setup, key_id)
+
+
+def rotate_setup_ring(ring: str, key_id: int) -> str:
+    """
+    Generate a new setup under a key id that is not in use yet, and make it the current one. New registrations use the
+    new setup, while logins with existing password files keep working.
+
+    :param ring:
+    :param key_id:
+    :return: Encoded setup ring.
+    """
+    return _internal.rotate_setup_ring_py(ring, key_id)
+
+
+def remove_setup_ring_key(ring: str, key_id: int) -> str:
+    """
+    Remove a retired setup from the ring. Logins with password files registered under it will fail. The current setup
+    cannot be removed.
+
+    :param ring:
+    :param key_id:
+    :return: Encoded setup ring.
+    """
+    return _internal.remove_setup_ring_key_py(ring, key_id)
+
+
+def register_ring(ring: str, client_request: str, credential_id: str) -> tuple[str, int]:
+    """
+    Perform the first step of registration on the server, using the current setup of the ring.
+
+    :param ring:
+    :param client_request:
+    :param credential_id:
+    :return: Tuple of encoded response to the client and the key id of the setup, which should be passed to
+    `register_finish`.
+    """
+    return _internal.register_server_ring_py(ring, client_request, credential_id)
+
+
+def password_file_key_id(password_file: str) -> Optional[int]:
+    """
+    :param password_file:
+    :return: Key id of the setup the password file was registered with, or None if it does not record one.
+    """
+    return _internal.password_file_key_id_py(password_file)
+
+
+def create_ksf_params(algorithm: str, m_cost: int, t_cost: int, p_cost: int) -> str:
+    """
+    Create custom parameters for the Argon2 key stretching function.
+
+    :param algorithm: One of 'argon2d', 'argon2i' or 'argon2id'.
+    :param m_cost: Memory cost in KiB.
+    :param t_cost: Number of iterations.
+    :param p_cost: Degree of parallelism.
+    :return: Encoded KSF parameters.
+    """
+    return _internal.create_ksf_params_py(algorithm, m_cost, t_cost, p_cost)
+
+
+def password_file_ksf_params(password_file: str) -> str:
+    """
+    Get the KSF parameters the client registered with, which must be passed to `login_client_finish`.
+
+    :param password_file:
+    :return: Encoded KSF parameters.
+    """
+    return _internal.password_file_ksf_params_py(password_file)
+
+
+def setup_public_key(setup: str) -> str:
+    """
+    Get the static public key of the server setup, which clients can pin by passing it as the `server_public_key` of
+    `login_client_finish`.
+
+    :param setup:
+    :return: Encoded public key.
+    """
+    return _internal.setup_public_key_py(setup)
 
 
-def register_client(password: str) -> tuple[str, str]:
+def setup_envelope(setup: str) -> str:
+    """
+    Convert a server setup to the versioned envelope format, which records the format version, the kind of object and
+    the cipher suite. All functions accept both envelopes and the plain format.
+
+    :param setup:
+    :return: Encoded server setup envelope.
+    """
+    return _internal.setup_envelope_py(setup)
+
+
+def password_file_envelope(password_file: str) -> str:
+    """
+    Convert a password file to the versioned envelope format. All functions accept both envelopes and the plain format.
+
+    :param password_file:
+    :return: Encoded password file envelope.
+    """
+    return _internal.password_file_envelope_py(password_file)
+
+
+def register_client(
+    password: str, ksf_params: Optional[str] = None, suite: Optional[str] = None
+) -> tuple[str, str]:
     """
     Perform the first registration step for the client.
 
     :param password:
+    :param ksf_params: Encoded KSF parameters, the Argon2 defaults are used if not provided.
+    :param suite: Cipher suite of the server setup, the default suite is used if not provided.
     :return: Tuple of encoded response to the server and register state to be saved, respectively.
     """
-    return _internal.register_client_py(password)
+    return _internal.register_client_py(password, ksf_params, suite)
 
 
 def register_client_finish(
-    client_register_state: str, password: str, server_message: str
-) -> str:
+    client_register_state: str,
+    password: str,
+    server_message: str,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+) -> tuple[str, str]:
     """
     Perform the final registration step for the client.
 
     :param client_register_state:
     :param password:
     :param server_message:
-    :return: Encoded response to the server.
+    :param client_identity: Identity of the client, such as the username. Must be the same during registration and every
+    login. Defaults to the client public key.
+    :param server_identity: Identity of the server, such as its hostname. Must be the same during registration and every
+    login. Defaults to the server public key.
+    :return: Tuple of encoded response to the server and encoded public key of the server, which can be compared to the
+    published one or passed as `server_public_key` to `login_client_finish`.
     """
     return _internal.register_client_finish_py(
-        client_register_state, password, server_message
+        client_register_state, password, server_message, client_identity, server_identity
     )
 
 
 def login(
-    setup: str, password_file: str, client_request: str, credential_id: str
+    setup: str,
+    password_file: Optional[str],
+    client_request: str,
+    credential_id: str,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
 ) -> tuple[str, str]:
     """
     Perform starting login step on the server.
 
     :param setup:
-    :param password_file:
+    :param password_file: Pass None if the user does not exist. A fake response is then returned, which is
+    indistinguishable from a real one, so that the login only fails at the finish step without revealing whether the
+    account exists.
     :param client_request:
     :param credential_id:
+    :param client_identity: Identity of the client, such as the username. Must be the same during registration and every
+    login. Defaults to the client public key.
+    :param server_identity: Identity of the server, such as its hostname. Must be the same during registration and every
+    login. Defaults to the server public key.
+    :param context: Context of the key exchange, such as the name of the application. Must be the same on the client
+    and server.
     :return: Tuple of encoded response to the client and login state to be saved, respectively.
     """
     return _internal.login_server_py(
-        setup, password_file, client_request, credential_id
+        setup, password_file, client_request, credential_id, client_identity, server_identity, context
+    )
+
+
+def login_ring(
+    ring: str,
+    password_file: Optional[str],
+    client_request: str,
+    credential_id: str,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+) -> tuple[str, str]:
+    """
+    Perform starting login step on the server, using the setup of the ring the password file was registered with. See
+    `login` for the parameters.
+
+    :return: Tuple of encoded response to the client and login state to be saved, respectively.
+    """
+    return _internal.login_server_ring_py(
+        ring, password_file, client_request, credential_id, client_identity, server_identity, context
     )
 
 
-def login_finish(client_request_finish: str, login_state: str) -> str:
+def login_finish(
+    client_request_finish: str,
+    login_state: str,
+    max_age: Optional[int] = None,
+    seen_nonce: Optional[Callable[[str, int], bool]] = None,
+) -> str:
     """
-    Finish the login process on the backend.
+    Finish the login process on the backend. Raises `InvalidState` if the state is older than `max_age` or if
+    `seen_nonce` reports it as used before.
 
     :param client_request_finish: Client request to finish login, base64url-encoded.
     :param login_state: Saved login state from the previous step, base64url-encoded.
+    :param max_age: Maximum age of the login state in seconds.
+    :param seen_nonce: Called with the encoded nonce of the state and the unix time until which it must be remembered,
+    returns whether the nonce is new. Back it with a shared store (e.g. Redis `SET NX`) to reject replayed states.
+    Requires `max_age`, as the nonces could never be forgotten otherwise.
     :return: The session key, base64url-encoded.
     """
-    return _internal.login_server_finish_py(client_request_finish, login_state)
+    return _internal.login_server_finish_py(client_request_finish, login_state, max_age, seen_nonce)
 
 
-def login_client(password: str) -> tuple[str, str]:
+def login_client(password: str, suite: Optional[str] = None) -> tuple[str, str]:
     """
     Perform the first step of login on the client.
 
     :param password:
+    :param suite: Cipher suite of the server setup, the default suite is used if not provided.
     :return: Tuple of encoded response to the server and login state to be saved, respectively.
     """
-    return _internal.login_client_py(password)
+    return _internal.login_client_py(password, suite)
 
 
 def login_client_finish(
-    client_login_state: str, password: str, server_message: str
-) -> tuple[str, str]:
+    client_login_state: str,
+    password: str,
+    server_message: str,
+    ksf_params: Optional[str] = None,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+    server_public_key: Optional[str] = None,
+) -> tuple[str, str, str, str]:
     """
     Finish the login process on the client. Generates a session key that will be equal to the one generated on the
     sever.
@@ -106,8 +303,417 @@ def login_client_finish(
     :param client_login_state:
     :param password:
     :param server_message:
-    :return: Tuple of encoded response to the server and session key, respectively.
+    :param ksf_params: Encoded KSF parameters used during registration, the Argon2 defaults are used if not provided.
+    :param client_identity: Identity of the client, such as the username. Must be the same during registration and every
+    login. Defaults to the client public key.
+    :param server_identity: Identity of the server, such as its hostname. Must be the same during registration and every
+    login. Defaults to the server public key.
+    :param context: Context of the key exchange, such as the name of the application. Must be the same on the client
+    and server.
+    :param server_public_key: Encoded public key the server is expected to use, as returned by `setup_public_key`. If
+    the server uses a different key, `InvalidLogin` is raised.
+    :return: Tuple of encoded response to the server, session key, export key and server public key, respectively. The
+    export key is only known to the client and is the same for every login with the same registration, so it can be used
+    for encrypting data client-side.
     """
     return _internal.login_client_finish_py(
-        client_login_state, password, server_message
+        client_login_state,
+        password,
+        server_message,
+        ksf_params,
+        client_identity,
+        server_identity,
+        context,
+        server_public_key,
+    )
+
+
+def change_password(
+    setup: str,
+    password_file: str,
+    client_request: str,
+    credential_id: str,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+) -> tuple[str, str]:
+    """
+    Perform the first step of a password change on the server, which starts a login using the current password file and
+    a registration of the new password. See `login` for the identities and context, which also apply to the new
+    password file.
+
+    :param setup:
+    :param password_file: The current password file of the user.
+    :param client_request:
+    :param credential_id:
+    :param client_identity:
+    :param server_identity:
+    :param context:
+    :return: Tuple of encoded response to the client and password change state to be saved, respectively.
+    """
+    return _internal.change_password_server_py(
+        setup, password_file, client_request, credential_id, client_identity, server_identity, context
+    )
+
+
+def change_password_ring(
+    ring: str,
+    password_file: str,
+    client_request: str,
+    credential_id: str,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+) -> tuple[str, str, int]:
+    """
+    Perform the first step of a password change on the server, using the setup of the ring the current password file
+    was registered with. See `change_password` for the parameters.
+
+    :return: Tuple of encoded response to the client, password change state to be saved and key id of the setup,
+    respectively. Pass the key id to `change_password_finish`, as the new password file uses the same setup.
+    """
+    return _internal.change_password_server_ring_py(
+        ring, password_file, client_request, credential_id, client_identity, server_identity, context
+    )
+
+
+def change_password_finish(
+    client_request_finish: str,
+    change_password_state: str,
+    key_id: Optional[int] = None,
+    max_age: Optional[int] = None,
+    seen_nonce: Optional[Callable[[str, int], bool]] = None,
+) -> tuple[str, str]:
+    """
+    Finish the password change on the server. This only succeeds if the client knew the old password, in which case
+    the new registration is accepted. The state is checked like in `login_finish`.
+
+    :param client_request_finish:
+    :param change_password_state:
+    :param key_id: Key id to record in the new password file when using a setup ring, as returned by
+    `change_password_ring`.
+    :param max_age: Maximum age of the password change state in seconds.
+    :param seen_nonce: See `login_finish`.
+    :return: Tuple of encoded new password file, which replaces the current one, and session key, respectively.
+    """
+    return _internal.change_password_server_finish_py(
+        client_request_finish, change_password_state, key_id, max_age, seen_nonce
+    )
+
+
+def change_password_client(
+    old_password: str, new_password: str, ksf_params: Optional[str] = None, suite: Optional[str] = None
+) -> tuple[str, str]:
+    """
+    Start a password change on the client. This logs in with the old password and registers the new one in the same
+    round trip.
+
+    :param old_password:
+    :param new_password:
+    :param ksf_params: Encoded KSF parameters for the new password, the Argon2 defaults are used if not provided.
+    :param suite: Cipher suite of the server setup, the default suite is used if not provided.
+    :return: Tuple of encoded message to the server and password change state, respectively.
+    """
+    return _internal.change_password_client_py(old_password, new_password, ksf_params, suite)
+
+
+def change_password_client_finish(
+    client_change_password_state: str,
+    old_password: str,
+    new_password: str,
+    server_message: str,
+    ksf_params: Optional[str] = None,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+    server_public_key: Optional[str] = None,
+) -> tuple[str, str, str, str]:
+    """
+    Finish the password change on the client. The new registration is authenticated with the session key, so that the
+    server only accepts it after a successful login with the old password.
+
+    :param client_change_password_state:
+    :param old_password:
+    :param new_password:
+    :param server_message:
+    :param ksf_params: Encoded KSF parameters of the current password file, the Argon2 defaults are used if not
+    provided.
+    :param client_identity:
+    :param server_identity:
+    :param context:
+    :param server_public_key: See `login_client_finish`.
+    :return: Tuple of encoded response to the server, session key, export key of the new password and export key of the
+    old password, respectively.
+    """
+    return _internal.change_password_client_finish_py(
+        client_change_password_state,
+        old_password,
+        new_password,
+        server_message,
+        ksf_params,
+        client_identity,
+        server_identity,
+        context,
+        server_public_key,
+    )
+
+
+def derive_key(shared_secret: str, label: str, length: int) -> str:
+    """
+    Derive a key from the session key of a login using HKDF-SHA512, with the label as info. Keys with different labels
+    are independent, and the client and server derive the same key from the same session key and label. This matches
+    `derive_key_wasm` in opaquewasm.
+
+    :param shared_secret: Encoded session key returned by `login_finish` or `login_client_finish`.
+    :param label: Purpose of the key, such as 'auth-token'.
+    :param length: Length of the key in bytes, at most 16320.
+    :return: Encoded derived key.
+    """
+    return _internal.derive_key_py(shared_secret, label, length)
+
+
+def session_tag(shared_secret: str) -> str:
+    """
+    Compute a short identifier of the session from its session key, which is equal on the client and server. It does
+    not reveal the session key, so it can be used as a session handle in cookies or logs. Matches `session_tag_wasm` in
+    opaquewasm.
+
+    :param shared_secret: Encoded session key returned by `login_finish` or `login_client_finish`.
+    :return: Encoded 16-byte session tag.
+    """
+    return _internal.session_tag_py(shared_secret)
+
+
+def verify_session_key(a: str, b: str) -> bool:
+    """
+    Compare two encoded session keys or keys derived from them in constant time, unlike `==` on the strings, which
+    can reveal how many leading characters are equal. Matches `verify_session_key_wasm` in opaquewasm.
+
+    :param a: Encoded key, e.g. returned by `login_finish` or `derive_key`.
+    :param b: Encoded key to compare with.
+    :return: Whether the keys are equal.
+    """
+    return _internal.verify_session_key_py(a, b)
+
+
+def verify_session_tag(shared_secret: str, tag: str) -> bool:
+    """
+    Check in constant time whether the tag is the `session_tag` of the session key, e.g. a tag sent by the client
+    to confirm it derived the same key. Matches `verify_session_tag_wasm` in opaquewasm.
+
+    :param shared_secret: Encoded session key returned by `login_finish` or `login_client_finish`.
+    :param tag: Encoded session tag.
+    :return: Whether the tag belongs to the session key.
+    """
+    return _internal.verify_session_tag_py(shared_secret, tag)
+
+
+def create_state_key() -> str:
+    """
+    Generate a key for sealing login states, so that they can be sent to the client between `login` and
+    `login_finish` instead of being stored on the server. Keep it secret and use the same key on all servers.
+
+    :return: Encoded state key.
+    """
+    return _internal.create_state_key_py()
+
+
+def seal_login_state(key: str, login_state: str, credential_id: str, ttl: int = 60) -> str:
+    """
+    Encrypt the login state returned by `login`, binding it to the user and letting it expire.
+
+    :param key: Encoded state key from `create_state_key`.
+    :param login_state:
+    :param credential_id: The user the login was started for.
+    :param ttl: Number of seconds after which the state expires.
+    :return: Encoded sealed state, which can be sent to the client.
+    """
+    return _internal.seal_login_state_py(key, login_state, credential_id, ttl)
+
+
+def login_finish_sealed(
+    client_request_finish: str,
+    sealed_state: str,
+    key: str,
+    credential_id: str,
+    max_age: Optional[int] = None,
+    seen_nonce: Optional[Callable[[str, int], bool]] = None,
+) -> str:
+    """
+    Finish the login on the server using a sealed login state. Raises `InvalidLogin` if the state was sealed with
+    another key or for another user or was modified, and `InvalidState` if it has expired or was used before.
+
+    :param client_request_finish:
+    :param sealed_state: Encoded state returned by `seal_login_state`.
+    :param key:
+    :param credential_id:
+    :param max_age: See `login_finish`. Use the `ttl` the state was sealed with.
+    :param seen_nonce: See `login_finish`. Sealed states are held by the client, so this is the only way to prevent
+    them from being used twice before they expire.
+    :return: Encoded session key.
+    """
+    return _internal.login_server_finish_sealed_py(
+        client_request_finish, sealed_state, key, credential_id, max_age, seen_nonce
+    )
+
+
+# The functions above release the GIL while running Argon2 and the OPRF, so they can run in a thread without blocking
+# other Python threads. The async variants below run them in the default thread pool of the event loop.
+
+
+async def register_async(setup: str, client_request: str, credential_id: str) -> str:
+    """
+    Like `register`, but runs in a thread so that it does not block the event loop.
+    """
+    return await asyncio.to_thread(register, setup, client_request, credential_id)
+
+
+async def register_ring_async(ring: str, client_request: str, credential_id: str) -> tuple[str, int]:
+    """
+    Like `register_ring`, but runs in a thread so that it does not block the event loop.
+    """
+    return await asyncio.to_thread(register_ring, ring, client_request, credential_id)
+
+
+async def register_client_finish_async(
+    client_register_state: str,
+    password: str,
+    server_message: str,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+) -> tuple[str, str]:
+    """
+    Like `register_client_finish`, but runs Argon2 in a thread so that it does not block the event loop.
+    """
+    return await asyncio.to_thread(
+        register_client_finish, client_register_state, password, server_message, client_identity, server_identity
+    )
+
+
+async def login_async(
+    setup: str,
+    password_file: Optional[str],
+    client_request: str,
+    credential_id: str,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+) -> tuple[str, str]:
+    """
+    Like `login`, but runs in a thread so that it does not block the event loop.
+    """
+    return await asyncio.to_thread(
+        login, setup, password_file, client_request, credential_id, client_identity, server_identity, context
+    )
+
+
+async def login_ring_async(
+    ring: str,
+    password_file: Optional[str],
+    client_request: str,
+    credential_id: str,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+) -> tuple[str, str]:
+    """
+    Like `login_ring`, but runs in a thread so that it does not block the event loop.
+    """
+    return await asyncio.to_thread(
+        login_ring, ring, password_file, client_request, credential_id, client_identity, server_identity, context
+    )
+
+
+async def login_client_finish_async(
+    client_login_state: str,
+    password: str,
+    server_message: str,
+    ksf_params: Optional[str] = None,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+    server_public_key: Optional[str] = None,
+) -> tuple[str, str, str, str]:
+    """
+    Like `login_client_finish`, but runs Argon2 in a thread so that it does not block the event loop.
+    """
+    return await asyncio.to_thread(
+        login_client_finish,
+        client_login_state,
+        password,
+        server_message,
+        ksf_params,
+        client_identity,
+        server_identity,
+        context,
+        server_public_key,
+    )
+
+
+async def change_password_async(
+    setup: str,
+    password_file: str,
+    client_request: str,
+    credential_id: str,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+) -> tuple[str, str]:
+    """
+    Like `change_password`, but runs in a thread so that it does not block the event loop.
+    """
+    return await asyncio.to_thread(
+        change_password, setup, password_file, client_request, credential_id, client_identity, server_identity, context
+    )
+
+
+async def change_password_ring_async(
+    ring: str,
+    password_file: str,
+    client_request: str,
+    credential_id: str,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+) -> tuple[str, str, int]:
+    """
+    Like `change_password_ring`, but runs in a thread so that it does not block the event loop.
+    """
+    return await asyncio.to_thread(
+        change_password_ring,
+        ring,
+        password_file,
+        client_request,
+        credential_id,
+        client_identity,
+        server_identity,
+        context,
+    )
+
+
+async def change_password_client_finish_async(
+    client_change_password_state: str,
+    old_password: str,
+    new_password: str,
+    server_message: str,
+    ksf_params: Optional[str] = None,
+    client_identity: Optional[str] = None,
+    server_identity: Optional[str] = None,
+    context: Optional[str] = None,
+    server_public_key: Optional[str] = None,
+) -> tuple[str, str, str, str]:
+    """
+    Like `change_password_client_finish`, but runs Argon2 in a thread so that it does not block the event loop.
+    """
+    return await asyncio.to_thread(
+        change_password_client_finish,
+        client_change_password_state,
+        old_password,
+        new_password,
+        server_message,
+        ksf_params,
+        client_identity,
+        server_identity,
+        context,
+        server_public_key,
     )
@@ -1,13 +1,35 @@
-use opaque_borink::client::{
-    client_login_finish, client_login_start, client_register_finish, client_register_start, ClientStateLogin, ClientStateRegistration
+use opaque_borink::client::{ClientStateChangePassword, ClientStateLogin, ClientStateRegistration};
+use opaque_borink::server::{KeyId, PasswordFile, ServerSetup, ServerSetupRing, LEGACY_KEY_ID};
+use opaque_borink::typed::{
+    client_change_password_finish, client_change_password_start, client_login_finish, client_login_start, client_register_finish, client_register_start,
+    server_change_password_finish, server_change_password_start, server_login_finish, server_login_start, server_register_finish, server_register_start,
+    ChangePasswordFinishRequest, ChangePasswordStartRequest, ChangePasswordStartResponse, LoginFinishRequest, LoginServerState, LoginStartRequest, LoginStartResponse,
+    RegistrationRequestBytes, RegistrationResponseBytes, RegistrationUploadBytes
 };
-use opaque_borink::server::{
-    server_login_finish, server_login_start, server_register_finish, server_register_start, PasswordFile, ServerSetup
-};
-use opaque_borink::encoded::{encode_bytes, decode_string};
-use opaque_borink::Error;
+use opaque_borink::client::{LOGIN_CLIENT_STATE_LEN, REGISTER_CLIENT_STATE_LEN};
+use opaque_borink::server::{LOGIN_SERVER_STATE_LEN, PASSWORD_FILE_REGISTRATION_LEN, SERVER_SETUP_LEN};
+use opaque_borink::encoded::{encode_bytes, decode_secret, decode_string};
+use opaque_borink::ksf::{Algorithm, KsfParams};
+use opaque_borink::replay::{SeenNonces, StateChecks, STATE_NONCE_LEN};
+use opaque_borink::sealed::{seal_login_state, unseal_login_state, StateKey};
+use opaque_borink::session::{derive_key, session_tag, verify_session_key, verify_session_tag};
+use opaque_borink::suite::SuiteId;
+use opaque_borink::{with_suite, Error, ErrorKind, Identifiers};
+use pyo3::create_exception;
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use std::time::Duration;
+
+// One exception class per `ErrorKind`, named by `ErrorKind::name`. The base class subclasses
+// `ValueError` so that existing handlers keep working.
+create_exception!(opaquepy, OpaqueError, PyValueError, "Base class of all errors raised by opaquepy.");
+create_exception!(opaquepy, InvalidLogin, OpaqueError, "The password is wrong, or the identities or context do not match.");
+create_exception!(opaquepy, DecodeError, OpaqueError, "Input is not valid base64url.");
+create_exception!(opaquepy, SerializationError, OpaqueError, "A message, state, setup or password file is malformed.");
+create_exception!(opaquepy, WrongObjectKind, SerializationError, "A serialized object is of a different kind or cipher suite than expected.");
+create_exception!(opaquepy, InvalidState, OpaqueError, "A step was called out of order.");
+create_exception!(opaquepy, InvalidParameters, OpaqueError, "Invalid KSF parameters or an unknown cipher suite.");
+create_exception!(opaquepy, InternalError, OpaqueError, "A failure inside opaque-ke that is not caused by the input.");
 
 pub type OpaquePyResult<T> = Result<T, OpaquePyError>;
 
@@ -20,11 +42,46 @@ impl From<Error> for OpaquePyError {
 }
 
 impl From<OpaquePyError> for PyErr {
+    /// The exception gets a `code` attribute with the stable error code.
     fn from(e: OpaquePyError) -> Self {
-        match e.0 {
-            Error::ProtocolError(oe) => PyValueError::new_err(format!("{:?}", oe)),
-            Error::DecodeError(oe) => PyValueError::new_err(oe.to_string()),
-        }
+        let message = e.0.to_string();
+        let kind = e.0.kind();
+        let err = match kind {
+            ErrorKind::InvalidLogin => InvalidLogin::new_err(message),
+            ErrorKind::MalformedMessage => SerializationError::new_err(message),
+            ErrorKind::WrongObjectKind => WrongObjectKind::new_err(message),
+            ErrorKind::InvalidBase64 => DecodeError::new_err(message),
+            ErrorKind::InvalidState => InvalidState::new_err(message),
+            ErrorKind::InvalidParameters => InvalidParameters::new_err(message),
+            ErrorKind::Internal => InternalError::new_err(message),
+        };
+        Python::with_gil(|py| {
+            // Setting an attribute on a fresh exception instance does not fail
+            let _ = err.value(py).setattr("code", kind.code());
+        });
+
+        err
+    }
+}
+
+fn decode_ksf_params(ksf_params: Option<&str>) -> OpaquePyResult<KsfParams> {
+    match ksf_params {
+        Some(ksf_params) => Ok(KsfParams::deserialize(&decode_string(ksf_params)?)?),
+        None => Ok(KsfParams::default()),
+    }
+}
+
+fn parse_suite(suite: Option<&str>) -> OpaquePyResult<SuiteId> {
+    match suite {
+        Some(suite) => Ok(suite.parse()?),
+        None => Ok(SuiteId::default()),
+    }
+}
+
+fn identifiers<'a>(client_identity: Option<&'a str>, server_identity: Option<&'a str>) -> Identifiers<'a> {
+    Identifiers {
+        client: client_identity.map(str::as_bytes),
+        server: server_identity.map(str::as_bytes),
     }
 }
 
@@ -40,129 +97,678 @@ fn opaquepy(m: &Bound<'_, PyModule>) -> PyResult<()> {
     internal.add_function(wrap_pyfunction!(login_server_finish_py, &internal)?)?;
     internal.add_function(wrap_pyfunction!(login_client_py, &internal)?)?;
     internal.add_function(wrap_pyfunction!(login_client_finish_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(create_ksf_params_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(password_file_ksf_params_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(setup_envelope_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(password_file_envelope_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(setup_public_key_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(create_setup_ring_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(setup_ring_from_setup_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(rotate_setup_ring_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(remove_setup_ring_key_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(register_server_ring_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(login_server_ring_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(password_file_key_id_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(change_password_server_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(change_password_server_ring_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(change_password_server_finish_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(change_password_client_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(change_password_client_finish_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(derive_key_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(session_tag_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(verify_session_key_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(verify_session_tag_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(create_state_key_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(seal_login_state_py, &internal)?)?;
+    internal.add_function(wrap_pyfunction!(login_server_finish_sealed_py, &internal)?)?;
 
     m.add_submodule(&internal)?;
 
+    m.add("OpaqueError", m.py().get_type::<OpaqueError>())?;
+    m.add("InvalidLogin", m.py().get_type::<InvalidLogin>())?;
+    m.add("DecodeError", m.py().get_type::<DecodeError>())?;
+    m.add("SerializationError", m.py().get_type::<SerializationError>())?;
+    m.add("WrongObjectKind", m.py().get_type::<WrongObjectKind>())?;
+    m.add("InvalidState", m.py().get_type::<InvalidState>())?;
+    m.add("InvalidParameters", m.py().get_type::<InvalidParameters>())?;
+    m.add("InternalError", m.py().get_type::<InternalError>())?;
+
     Ok(())
 }
 
 #[pyfunction]
-fn create_setup_py() -> String {
-    encode_bytes(&ServerSetup::create().serialize())
+#[pyo3(signature = (suite=None))]
+fn create_setup_py(suite: Option<&str>) -> OpaquePyResult<String> {
+    let suite = parse_suite(suite)?;
+
+    Ok(with_suite!(suite, CS => encode_bytes(&ServerSetup::<CS>::create().serialize())))
 }
 
 #[pyfunction]
 fn register_server_py(
+    py: Python<'_>,
     setup: &str,
     client_request: &str,
     credential_id: &str,
 ) -> OpaquePyResult<String> {
-    let setup_bytes = decode_string(setup)?;
-    let setup = ServerSetup::deserialize(&setup_bytes)?;
-    let result = server_register_start(&mut setup.view(), &decode_string(client_request)?, credential_id.as_bytes())?;
+    py.allow_threads(|| {
+        let setup_bytes = decode_secret(setup)?;
+        let suite = SuiteId::from_serialized(&setup_bytes, SERVER_SETUP_LEN)?;
+        let client_request = RegistrationRequestBytes::from_base64(client_request)?;
+
+        let result = with_suite!(suite, CS => {
+            let setup = ServerSetup::<CS>::deserialize(&setup_bytes)?;
+            let mut view = setup.view();
+            server_register_start(&mut view, &client_request, credential_id.as_bytes())?
+        });
+
+        Ok(result.response.to_base64())
+    })
+}
 
-    Ok(encode_bytes(&result.response))
+#[pyfunction]
+#[pyo3(signature = (client_request_finish, suite=None, key_id=None))]
+fn register_server_finish_py(client_request_finish: &str, suite: Option<&str>, key_id: Option<KeyId>) -> OpaquePyResult<String> {
+    let suite = parse_suite(suite)?;
+    let client_request_finish = RegistrationUploadBytes::from_base64(client_request_finish)?;
+
+    let password_file = with_suite!(suite, CS => {
+        let mut password_file = server_register_finish::<CS>(&client_request_finish)?;
+        password_file.set_key_id(key_id);
+        password_file.serialize()
+    });
+
+    Ok(encode_bytes(&password_file))
 }
 
+/// Legacy length of objects that have no untagged legacy form, such as setup rings, which no input
+/// has.
+const NO_LEGACY_LEN: usize = usize::MAX;
+
 #[pyfunction]
-fn register_server_finish_py(client_request_finish: &str) -> OpaquePyResult<String> {
-    let result = server_register_finish(&decode_string(client_request_finish)?)?;
+#[pyo3(signature = (key_id=0, suite=None))]
+fn create_setup_ring_py(key_id: KeyId, suite: Option<&str>) -> OpaquePyResult<String> {
+    let suite = parse_suite(suite)?;
+
+    let ring = with_suite!(suite, CS => ServerSetupRing::<CS>::new(key_id, ServerSetup::<CS>::create()).serialize());
 
-    Ok(encode_bytes(&result.serialize()))
+    Ok(encode_bytes(&ring))
 }
 
 #[pyfunction]
-fn register_client_py(password: &str) -> OpaquePyResult<(String, String)> {
-    let mut client_state = ClientStateRegistration::setup();
+#[pyo3(signature = (setup, key_id=0))]
+fn setup_ring_from_setup_py(setup: &str, key_id: KeyId) -> OpaquePyResult<String> {
+    let setup = decode_secret(setup)?;
+    let suite = SuiteId::from_serialized(&setup, SERVER_SETUP_LEN)?;
+
+    let ring = with_suite!(suite, CS => ServerSetupRing::<CS>::new(key_id, ServerSetup::<CS>::deserialize(&setup)?).serialize());
+
+    Ok(encode_bytes(&ring))
+}
+
+#[pyfunction]
+fn rotate_setup_ring_py(ring: &str, key_id: KeyId) -> OpaquePyResult<String> {
+    let ring = decode_secret(ring)?;
+    let suite = SuiteId::from_serialized(&ring, NO_LEGACY_LEN)?;
+
+    let ring = with_suite!(suite, CS => {
+        let mut ring = ServerSetupRing::<CS>::deserialize(&ring)?;
+        ring.rotate(key_id)?;
+        ring.serialize()
+    });
+
+    Ok(encode_bytes(&ring))
+}
+
+#[pyfunction]
+fn remove_setup_ring_key_py(ring: &str, key_id: KeyId) -> OpaquePyResult<String> {
+    let ring = decode_secret(ring)?;
+    let suite = SuiteId::from_serialized(&ring, NO_LEGACY_LEN)?;
+
+    let ring = with_suite!(suite, CS => {
+        let mut ring = ServerSetupRing::<CS>::deserialize(&ring)?;
+        ring.remove(key_id)?;
+        ring.serialize()
+    });
+
+    Ok(encode_bytes(&ring))
+}
+
+#[pyfunction]
+fn register_server_ring_py(
+    py: Python<'_>,
+    ring: &str,
+    client_request: &str,
+    credential_id: &str,
+) -> OpaquePyResult<(String, KeyId)> {
+    py.allow_threads(|| {
+        let ring = decode_secret(ring)?;
+        let suite = SuiteId::from_serialized(&ring, NO_LEGACY_LEN)?;
+        let client_request = RegistrationRequestBytes::from_base64(client_request)?;
+
+        let (result, key_id) = with_suite!(suite, CS => {
+            let ring = ServerSetupRing::<CS>::deserialize(&ring)?;
+            let mut view = ring.current().view();
+            let result = server_register_start(&mut view, &client_request, credential_id.as_bytes())?;
+            (result, ring.current_key_id())
+        });
+
+        Ok((result.response.to_base64(), key_id))
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (ring, password_file, client_request, credential_id, client_identity=None, server_identity=None, context=None))]
+#[allow(clippy::too_many_arguments)]
+fn login_server_ring_py(
+    py: Python<'_>,
+    ring: &str,
+    password_file: Option<&str>,
+    client_request: &str,
+    credential_id: &str,
+    client_identity: Option<&str>,
+    server_identity: Option<&str>,
+    context: Option<&str>,
+) -> OpaquePyResult<(String, String)> {
+    py.allow_threads(|| {
+        let ring = decode_secret(ring)?;
+        let suite = SuiteId::from_serialized(&ring, NO_LEGACY_LEN)?;
+        let password_file = password_file.map(decode_string).transpose()?;
+        let client_request = LoginStartRequest::from_base64(client_request)?;
+
+        let result = with_suite!(suite, CS => {
+            let ring = ServerSetupRing::<CS>::deserialize(&ring)?;
+            let password_file = password_file
+                .map(|password_file| PasswordFile::<CS>::deserialize(&password_file))
+                .transpose()?;
+            let mut view = ring.view_for(password_file.as_ref())?;
+
+            server_login_start(
+                &mut view,
+                password_file.as_ref(),
+                &client_request,
+                credential_id,
+                identifiers(client_identity, server_identity),
+                context.map(str::as_bytes),
+            )?
+        });
+
+        let response_encoded = result.response.to_base64();
+        let state_encoded = result.state.to_base64();
+
+        Ok((response_encoded, state_encoded))
+    })
+}
+
+#[pyfunction]
+fn password_file_key_id_py(password_file: &str) -> OpaquePyResult<Option<KeyId>> {
+    let password_file = decode_string(password_file)?;
+    let suite = SuiteId::from_serialized(&password_file, PASSWORD_FILE_REGISTRATION_LEN)?;
+
+    Ok(with_suite!(suite, CS => PasswordFile::<CS>::deserialize(&password_file)?.key_id()))
+}
+
+#[pyfunction]
+fn create_ksf_params_py(
+    algorithm: &str,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+) -> OpaquePyResult<String> {
+    let algorithm = Algorithm::new(algorithm).map_err(Error::from)?;
+    let ksf_params = KsfParams::new(algorithm, m_cost, t_cost, p_cost)?;
+
+    Ok(encode_bytes(&ksf_params.serialize()))
+}
+
+#[pyfunction]
+fn password_file_ksf_params_py(password_file: &str) -> OpaquePyResult<String> {
+    let password_file = decode_string(password_file)?;
+    let suite = SuiteId::from_serialized(&password_file, PASSWORD_FILE_REGISTRATION_LEN)?;
+
+    let ksf_params = with_suite!(suite, CS => PasswordFile::<CS>::deserialize(&password_file)?.ksf_params());
+
+    Ok(encode_bytes(&ksf_params.serialize()))
+}
+
+#[pyfunction]
+fn setup_public_key_py(setup: &str) -> OpaquePyResult<String> {
+    let setup = decode_secret(setup)?;
+    let suite = SuiteId::from_serialized(&setup, SERVER_SETUP_LEN)?;
+
+    let public_key = with_suite!(suite, CS => ServerSetup::<CS>::deserialize(&setup)?.public_key());
+
+    Ok(encode_bytes(&public_key))
+}
+
+#[pyfunction]
+fn setup_envelope_py(setup: &str) -> OpaquePyResult<String> {
+    let setup = decode_secret(setup)?;
+    let suite = SuiteId::from_serialized(&setup, SERVER_SETUP_LEN)?;
+
+    let envelope = with_suite!(suite, CS => ServerSetup::<CS>::deserialize(&setup)?.serialize_envelope());
+
+    Ok(encode_bytes(&envelope))
+}
+
+#[pyfunction]
+fn password_file_envelope_py(password_file: &str) -> OpaquePyResult<String> {
+    let password_file = decode_string(password_file)?;
+    let suite = SuiteId::from_serialized(&password_file, PASSWORD_FILE_REGISTRATION_LEN)?;
+
+    let envelope = with_suite!(suite, CS => PasswordFile::<CS>::deserialize(&password_file)?.serialize_envelope());
+
+    Ok(encode_bytes(&envelope))
+}
 
-    let result = client_register_start(&mut client_state, password.as_bytes())?;
-    let message_encoded = encode_bytes(&result.response);
-    let state_encoded = encode_bytes(&client_state.serialize());
+#[pyfunction]
+#[pyo3(signature = (password, ksf_params=None, suite=None))]
+fn register_client_py(password: &str, ksf_params: Option<&str>, suite: Option<&str>) -> OpaquePyResult<(String, String)> {
+    let suite = parse_suite(suite)?;
+    let ksf_params = decode_ksf_params(ksf_params)?;
+
+    let (result, state) = with_suite!(suite, CS => {
+        let mut client_state = ClientStateRegistration::<CS>::setup();
+        let result = client_register_start(&mut client_state, password.as_bytes(), ksf_params)?;
+        (result, client_state.serialize()?)
+    });
+    let message_encoded = result.response.to_base64();
+    let state_encoded = encode_bytes(&state);
 
     Ok((message_encoded, state_encoded))
 }
 
 #[pyfunction]
+#[pyo3(signature = (client_register_state, password, server_message, client_identity=None, server_identity=None))]
 fn register_client_finish_py(
+    py: Python<'_>,
     client_register_state: &str,
     password: &str,
     server_message: &str,
-) -> OpaquePyResult<String> {
-    let client_register_state = decode_string(client_register_state)?;
-    let mut client_state = ClientStateRegistration::deserialize(&client_register_state)?;
-    let server_message = decode_string(server_message)?;
-    let result = client_register_finish(&mut client_state, password.as_bytes(), &server_message)?;
-
-    Ok(encode_bytes(&result.response))
+    client_identity: Option<&str>,
+    server_identity: Option<&str>,
+) -> OpaquePyResult<(String, String)> {
+    py.allow_threads(|| {
+        let client_register_state = decode_secret(client_register_state)?;
+        let suite = SuiteId::from_serialized(&client_register_state, REGISTER_CLIENT_STATE_LEN)?;
+        let server_message = RegistrationResponseBytes::from_base64(server_message)?;
+
+        let result = with_suite!(suite, CS => {
+            let mut client_state = ClientStateRegistration::<CS>::deserialize(&client_register_state)?;
+            client_register_finish(
+                &mut client_state,
+                password.as_bytes(),
+                &server_message,
+                identifiers(client_identity, server_identity),
+            )?
+        });
+
+        Ok((result.response.to_base64(), encode_bytes(&result.server_public_key)))
+    })
 }
 
 #[pyfunction]
+#[pyo3(signature = (setup, password_file, client_request, credential_id, client_identity=None, server_identity=None, context=None))]
+#[allow(clippy::too_many_arguments)]
 fn login_server_py(
+    py: Python<'_>,
     setup: &str,
-    password_file: &str,
+    password_file: Option<&str>,
     client_request: &str,
     credential_id: &str,
+    client_identity: Option<&str>,
+    server_identity: Option<&str>,
+    context: Option<&str>,
 ) -> OpaquePyResult<(String, String)> {
-    let setup = ServerSetup::deserialize(&decode_string(setup)?)?;
-    let password_file = PasswordFile::deserialize(&decode_string(password_file)?)?;
-    let client_request = decode_string(client_request)?;
-
-    let result = server_login_start(
-        &mut setup.view(),
-        &password_file,
-        &client_request,
-        credential_id,
-    )?;
+    py.allow_threads(|| {
+        let setup = decode_secret(setup)?;
+        let suite = SuiteId::from_serialized(&setup, SERVER_SETUP_LEN)?;
+        let password_file = password_file.map(decode_string).transpose()?;
+        let client_request = LoginStartRequest::from_base64(client_request)?;
+
+        let result = with_suite!(suite, CS => {
+            let setup = ServerSetup::<CS>::deserialize(&setup)?;
+            let password_file = password_file
+                .map(|password_file| PasswordFile::<CS>::deserialize(&password_file))
+                .transpose()?;
+            let mut view = setup.view();
+
+            server_login_start(
+                &mut view,
+                password_file.as_ref(),
+                &client_request,
+                credential_id,
+                identifiers(client_identity, server_identity),
+                context.map(str::as_bytes),
+            )?
+        });
+
+        let response_encoded = result.response.to_base64();
+        let state_encoded = result.state.to_base64();
+
+        Ok((response_encoded, state_encoded))
+    })
+}
 
-    let response_encoded = encode_bytes(&result.response);
-    let state_encoded = encode_bytes(&result.state);
+/// Calls a Python `seen_nonce(nonce, expires_at)` function, which returns whether the encoded nonce
+/// is new.
+struct PySeenNonces<'py>(Bound<'py, PyAny>);
 
-    Ok((response_encoded, state_encoded))
+impl SeenNonces for PySeenNonces<'_> {
+    fn insert(&self, nonce: &[u8; STATE_NONCE_LEN], expires_at: u64) -> Result<bool, Error> {
+        self.0.call1((encode_bytes(nonce), expires_at)).and_then(|new| new.is_truthy())
+        .map_err(|e| Error::NonceStore(e.to_string()))
+    }
 }
 
 #[pyfunction]
+#[pyo3(signature = (client_request_finish, login_state, max_age=None, seen_nonce=None))]
 fn login_server_finish_py(
     client_request_finish: &str,
     login_state: &str,
+    max_age: Option<u64>,
+    seen_nonce: Option<Bound<'_, PyAny>>,
 ) -> OpaquePyResult<String> {
-    let client_request_finish = decode_string(client_request_finish)?;
-    let login_state = decode_string(login_state)?;
+    let client_request_finish = LoginFinishRequest::from_base64(client_request_finish)?;
+    let login_state = LoginServerState::from_base64(login_state)?;
+    let suite = SuiteId::from_serialized(login_state.as_bytes(), LOGIN_SERVER_STATE_LEN)?;
+    let seen_nonce = seen_nonce.map(PySeenNonces);
+    let checks = StateChecks { max_age: max_age.map(Duration::from_secs), seen_nonces: seen_nonce.as_ref().map(|s| s as &dyn SeenNonces) };
 
-    let result = server_login_finish(&client_request_finish, &login_state)?;
+    let result = with_suite!(suite, CS => server_login_finish::<CS>(&client_request_finish, &login_state, &checks)?);
 
     Ok(encode_bytes(&result.shared_secret))
 }
 
 #[pyfunction]
-fn login_client_py(password: &str) -> OpaquePyResult<(String, String)> {
-    let mut client_state = ClientStateLogin::setup();
+#[pyo3(signature = (password, suite=None))]
+fn login_client_py(password: &str, suite: Option<&str>) -> OpaquePyResult<(String, String)> {
+    let suite = parse_suite(suite)?;
 
-    let result = client_login_start(&mut client_state, password.as_bytes())?;
+    let (result, state) = with_suite!(suite, CS => {
+        let mut client_state = ClientStateLogin::<CS>::setup();
+        let result = client_login_start(&mut client_state, password.as_bytes())?;
+        (result, client_state.serialize()?)
+    });
 
-    let message_encoded = encode_bytes(&result.response);
-    let state_encoded = encode_bytes(&client_state.serialize());
+    let message_encoded = result.response.to_base64();
+    let state_encoded = encode_bytes(&state);
 
     Ok((message_encoded, state_encoded))
 }
 
 #[pyfunction]
+#[pyo3(signature = (client_login_state, password, server_message, ksf_params=None, client_identity=None, server_identity=None, context=None, server_public_key=None))]
+#[allow(clippy::too_many_arguments)]
 fn login_client_finish_py(
+    py: Python<'_>,
     client_login_state: &str,
     password: &str,
     server_message: &str,
+    ksf_params: Option<&str>,
+    client_identity: Option<&str>,
+    server_identity: Option<&str>,
+    context: Option<&str>,
+    server_public_key: Option<&str>,
+) -> OpaquePyResult<(String, String, String, String)> {
+    py.allow_threads(|| {
+        let client_login_state = decode_secret(client_login_state)?;
+        let suite = SuiteId::from_serialized(&client_login_state, LOGIN_CLIENT_STATE_LEN)?;
+        let server_message = LoginStartResponse::from_base64(server_message)?;
+        let ksf_params = decode_ksf_params(ksf_params)?;
+        let server_public_key = server_public_key.map(decode_string).transpose()?;
+
+        let result = with_suite!(suite, CS => {
+            let mut client_state = ClientStateLogin::<CS>::deserialize(&client_login_state)?;
+            client_login_finish(
+                &mut client_state,
+                password.as_bytes(),
+                &server_message,
+                ksf_params,
+                identifiers(client_identity, server_identity),
+                context.map(str::as_bytes),
+                server_public_key.as_deref(),
+            )?
+        });
+    
+        let message_encoded = result.response.to_base64();
+        let shared_secret_encoded = encode_bytes(&result.shared_secret);
+        let export_key_encoded = encode_bytes(&result.export_key);
+        let server_public_key_encoded = encode_bytes(&result.server_public_key);
+
+        Ok((message_encoded, shared_secret_encoded, export_key_encoded, server_public_key_encoded))
+    })
+}
+
+#[pyfunction]
+#[pyo3(signature = (setup, password_file, client_request, credential_id, client_identity=None, server_identity=None, context=None))]
+#[allow(clippy::too_many_arguments)]
+fn change_password_server_py(
+    py: Python<'_>,
+    setup: &str,
+    password_file: &str,
+    client_request: &str,
+    credential_id: &str,
+    client_identity: Option<&str>,
+    server_identity: Option<&str>,
+    context: Option<&str>,
 ) -> OpaquePyResult<(String, String)> {
+    py.allow_threads(|| {
+        let setup = decode_secret(setup)?;
+        let suite = SuiteId::from_serialized(&setup, SERVER_SETUP_LEN)?;
+        let password_file = decode_string(password_file)?;
+        let client_request = ChangePasswordStartRequest::from_base64(client_request)?;
+
+        let result = with_suite!(suite, CS => {
+            let setup = ServerSetup::<CS>::deserialize(&setup)?;
+            let password_file = PasswordFile::<CS>::deserialize(&password_file)?;
+            let mut view = setup.view();
+
+            server_change_password_start(
+                &mut view,
+                &password_file,
+                &client_request,
+                credential_id,
+                identifiers(client_identity, server_identity),
+                context.map(str::as_bytes),
+            )?
+        });
+
+        let response_encoded = result.response.to_base64();
+        let state_encoded = result.state.to_base64();
+
+        Ok((response_encoded, state_encoded))
+    })
+}
 
-    let mut client_state = ClientStateLogin::deserialize(&decode_string(client_login_state)?)?;
-    let server_message = decode_string(server_message)?;
+#[pyfunction]
+#[pyo3(signature = (ring, password_file, client_request, credential_id, client_identity=None, server_identity=None, context=None))]
+#[allow(clippy::too_many_arguments)]
+fn change_password_server_ring_py(
+    py: Python<'_>,
+    ring: &str,
+    password_file: &str,
+    client_request: &str,
+    credential_id: &str,
+    client_identity: Option<&str>,
+    server_identity: Option<&str>,
+    context: Option<&str>,
+) -> OpaquePyResult<(String, String, KeyId)> {
+    py.allow_threads(|| {
+        let ring = decode_secret(ring)?;
+        let suite = SuiteId::from_serialized(&ring, NO_LEGACY_LEN)?;
+        let password_file = decode_string(password_file)?;
+        let client_request = ChangePasswordStartRequest::from_base64(client_request)?;
+
+        let (result, key_id) = with_suite!(suite, CS => {
+            let ring = ServerSetupRing::<CS>::deserialize(&ring)?;
+            let password_file = PasswordFile::<CS>::deserialize(&password_file)?;
+            let mut view = ring.view_for(Some(&password_file))?;
+
+            let result = server_change_password_start(
+                &mut view,
+                &password_file,
+                &client_request,
+                credential_id,
+                identifiers(client_identity, server_identity),
+                context.map(str::as_bytes),
+            )?;
+            (result, password_file.key_id().unwrap_or(LEGACY_KEY_ID))
+        });
+
+        Ok((result.response.to_base64(), result.state.to_base64(), key_id))
+    })
+}
 
-    let result = client_login_finish(
-        &mut client_state,
-        password.as_bytes(),
-        &server_message,
-    )?;
-    
-    let message_encoded = encode_bytes(&result.response);
-    let shared_secret_encoded = encode_bytes(&result.shared_secret);
+#[pyfunction]
+#[pyo3(signature = (client_request_finish, change_password_state, key_id=None, max_age=None, seen_nonce=None))]
+fn change_password_server_finish_py(
+    client_request_finish: &str,
+    change_password_state: &str,
+    key_id: Option<KeyId>,
+    max_age: Option<u64>,
+    seen_nonce: Option<Bound<'_, PyAny>>,
+) -> OpaquePyResult<(String, String)> {
+    let client_request_finish = ChangePasswordFinishRequest::from_base64(client_request_finish)?;
+    let change_password_state = LoginServerState::from_base64(change_password_state)?;
+    let suite = SuiteId::from_serialized(change_password_state.as_bytes(), LOGIN_SERVER_STATE_LEN)?;
+    let seen_nonce = seen_nonce.map(PySeenNonces);
+    let checks = StateChecks { max_age: max_age.map(Duration::from_secs), seen_nonces: seen_nonce.as_ref().map(|s| s as &dyn SeenNonces) };
+
+    let (password_file, shared_secret) = with_suite!(suite, CS => {
+        let mut result = server_change_password_finish::<CS>(&client_request_finish, &change_password_state, &checks)?;
+        result.password_file.set_key_id(key_id);
+        (result.password_file.serialize(), result.shared_secret)
+    });
+
+    Ok((encode_bytes(&password_file), encode_bytes(&shared_secret)))
+}
+
+#[pyfunction]
+#[pyo3(signature = (old_password, new_password, ksf_params=None, suite=None))]
+fn change_password_client_py(
+    old_password: &str,
+    new_password: &str,
+    ksf_params: Option<&str>,
+    suite: Option<&str>,
+) -> OpaquePyResult<(String, String)> {
+    let suite = parse_suite(suite)?;
+    let ksf_params = decode_ksf_params(ksf_params)?;
 
-    Ok((message_encoded, shared_secret_encoded))
+    let (result, state) = with_suite!(suite, CS => {
+        let mut client_state = ClientStateChangePassword::<CS>::setup();
+        let result = client_change_password_start(&mut client_state, old_password.as_bytes(), new_password.as_bytes(), ksf_params)?;
+        (result, client_state.serialize()?)
+    });
+
+    let message_encoded = result.response.to_base64();
+    let state_encoded = encode_bytes(&state);
+
+    Ok((message_encoded, state_encoded))
+}
+
+#[pyfunction]
+#[pyo3(signature = (client_change_password_state, old_password, new_password, server_message, ksf_params=None, client_identity=None, server_identity=None, context=None, server_public_key=None))]
+#[allow(clippy::too_many_arguments)]
+fn change_password_client_finish_py(
+    py: Python<'_>,
+    client_change_password_state: &str,
+    old_password: &str,
+    new_password: &str,
+    server_message: &str,
+    ksf_params: Option<&str>,
+    client_identity: Option<&str>,
+    server_identity: Option<&str>,
+    context: Option<&str>,
+    server_public_key: Option<&str>,
+) -> OpaquePyResult<(String, String, String, String)> {
+    py.allow_threads(|| {
+        let client_change_password_state = decode_secret(client_change_password_state)?;
+        let suite = SuiteId::from_serialized(&client_change_password_state, NO_LEGACY_LEN)?;
+        let server_message = ChangePasswordStartResponse::from_base64(server_message)?;
+        let ksf_params = decode_ksf_params(ksf_params)?;
+        let server_public_key = server_public_key.map(decode_string).transpose()?;
+
+        let result = with_suite!(suite, CS => {
+            let mut client_state = ClientStateChangePassword::<CS>::deserialize(&client_change_password_state)?;
+            client_change_password_finish(
+                &mut client_state,
+                old_password.as_bytes(),
+                new_password.as_bytes(),
+                &server_message,
+                ksf_params,
+                identifiers(client_identity, server_identity),
+                context.map(str::as_bytes),
+                server_public_key.as_deref(),
+            )?
+        });
+
+        let message_encoded = result.response.to_base64();
+        let shared_secret_encoded = encode_bytes(&result.shared_secret);
+        let export_key_encoded = encode_bytes(&result.export_key);
+        let old_export_key_encoded = encode_bytes(&result.old_export_key);
+
+        Ok((message_encoded, shared_secret_encoded, export_key_encoded, old_export_key_encoded))
+    })
+}
+
+#[pyfunction]
+fn derive_key_py(shared_secret: &str, label: &str, length: usize) -> OpaquePyResult<String> {
+    let shared_secret = decode_secret(shared_secret)?;
+
+    Ok(encode_bytes(&derive_key(&shared_secret, label.as_bytes(), length)?))
+}
+
+#[pyfunction]
+fn session_tag_py(shared_secret: &str) -> OpaquePyResult<String> {
+    let shared_secret = decode_secret(shared_secret)?;
+
+    Ok(encode_bytes(&session_tag(&shared_secret)))
+}
+
+#[pyfunction]
+fn verify_session_key_py(a: &str, b: &str) -> OpaquePyResult<bool> {
+    Ok(verify_session_key(&decode_secret(a)?, &decode_secret(b)?))
+}
+
+#[pyfunction]
+fn verify_session_tag_py(shared_secret: &str, tag: &str) -> OpaquePyResult<bool> {
+    Ok(verify_session_tag(&decode_secret(shared_secret)?, &decode_string(tag)?))
+}
+
+#[pyfunction]
+fn create_state_key_py() -> String {
+    encode_bytes(&StateKey::generate().to_bytes())
+}
+
+#[pyfunction]
+fn seal_login_state_py(key: &str, login_state: &str, credential_id: &str, ttl: u64) -> OpaquePyResult<String> {
+    let key = StateKey::from_bytes(&decode_secret(key)?)?;
+    let login_state = LoginServerState::from_base64(login_state)?;
+
+    let sealed = seal_login_state(&key, login_state.as_bytes(), credential_id, Duration::from_secs(ttl))?;
+
+    Ok(encode_bytes(&sealed))
+}
+
+#[pyfunction]
+#[pyo3(signature = (client_request_finish, sealed_state, key, credential_id, max_age=None, seen_nonce=None))]
+fn login_server_finish_sealed_py(
+    client_request_finish: &str,
+    sealed_state: &str,
+    key: &str,
+    credential_id: &str,
+    max_age: Option<u64>,
+    seen_nonce: Option<Bound<'_, PyAny>>,
+) -> OpaquePyResult<String> {
+    let client_request_finish = LoginFinishRequest::from_base64(client_request_finish)?;
+    let key = StateKey::from_bytes(&decode_secret(key)?)?;
+    let login_state = LoginServerState::from_bytes(&unseal_login_state(&key, &decode_string(sealed_state)?, credential_id)?)?;
+    let suite = SuiteId::from_serialized(login_state.as_bytes(), LOGIN_SERVER_STATE_LEN)?;
+    let seen_nonce = seen_nonce.map(PySeenNonces);
+    let checks = StateChecks { max_age: max_age.map(Duration::from_secs), seen_nonces: seen_nonce.as_ref().map(|s| s as &dyn SeenNonces) };
+
+    let result = with_suite!(suite, CS => server_login_finish::<CS>(&client_request_finish, &login_state, &checks)?);
+
+    Ok(encode_bytes(&result.shared_secret))
 }
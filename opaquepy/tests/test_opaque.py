@@ -1,4 +1,7 @@
-from typing import Generator
+import asyncio
+import json
+from pathlib import Path
+from typing import Generator, Optional
 import pytest
 
 from opaquepy import *
@@ -22,11 +25,48 @@ def client_registration() -> Generator[None, None, tuple[str]]:
     yield response, client_state
 
 
+def _register(
+    setup: str, password: str = password, suite: Optional[str] = None, ksf_params: Optional[str] = None, **ids: str
+) -> str:
+    """Registers "someperson", passing the identities to the client, and returns the password file."""
+    response, client_state = register_client(password, ksf_params, suite)
+    server_response = register(setup, response, "someperson")
+    client_final_response, _ = register_client_finish(client_state, password, server_response, **ids)
+    return register_finish(client_final_response, suite)
+
+
+def _login_start(
+    setup: str, password_file: Optional[str], password: str = password, suite: Optional[str] = None, **kw: str
+) -> tuple[str, str, str]:
+    """Starts a login of "someperson", passing the identities and context to the server. Returns the client login
+    state, server message and server login state."""
+    client_message, login_state = login_client(password, suite)
+    server_message, server_state = login(setup, password_file, client_message, "someperson", **kw)
+    return login_state, server_message, server_state
+
+
+def _login(
+    setup: str,
+    password_file: str,
+    password: str = password,
+    suite: Optional[str] = None,
+    ksf_params: Optional[str] = None,
+    **kw: str,
+) -> tuple[str, str, str, str]:
+    """Runs a login of "someperson" up to the server finish, passing the identities and context to both sides.
+    Returns the client finish message, server login state, session key and export key."""
+    login_state, server_message, server_state = _login_start(setup, password_file, password, suite, **kw)
+    client_finish, session_key, export_key, _ = login_client_finish(
+        login_state, password, server_message, ksf_params, **kw
+    )
+    return client_finish, server_state, session_key, export_key
+
+
 def test_register(server_setup: str, client_registration: tuple[str]):
     response = client_registration[0]
     client_state = client_registration[1]
     server_response = register(server_setup, response, "someperson")
-    client_final_response = register_client_finish(client_state, password, server_response)
+    client_final_response, _ = register_client_finish(client_state, password, server_response)
     assert client_final_response
 
 
@@ -47,3 +87,371 @@ def test_login_finish():
     assert login_finish(client_message, state)
 
 
+def test_custom_ksf_params(server_setup: str):
+    ksf_params = create_ksf_params("argon2i", 1024, 1, 1)
+    password_file = _register(server_setup, ksf_params=ksf_params)
+    assert password_file_ksf_params(password_file) == ksf_params
+
+    client_finish, server_state, session_key, _ = _login(
+        server_setup, password_file, ksf_params=password_file_ksf_params(password_file)
+    )
+    assert login_finish(client_finish, server_state) == session_key
+
+
+def test_invalid_ksf_params():
+    with pytest.raises(ValueError):
+        create_ksf_params("argon2id", 1, 1, 1)
+    with pytest.raises(InvalidParameters):
+        create_ksf_params("argon2id", 4 * 1024 * 1024, 2, 1)
+
+
+@pytest.mark.parametrize("suite", ["ristretto255_argon2", "p256_argon2", "p384_argon2"])
+def test_suites(suite: str):
+    setup = create_setup(suite)
+    password_file = _register(setup, suite=suite)
+
+    client_finish, server_state, session_key, _ = _login(setup, password_file, suite=suite)
+    assert login_finish(client_finish, server_state) == session_key
+
+
+def test_suite_mismatch():
+    with pytest.raises(ValueError):
+        create_setup("p521_argon2")
+
+    password_file = _register(create_setup("p256_argon2"), suite="p256_argon2")
+    client_message, _ = login_client(password)
+    with pytest.raises(ValueError):
+        login(create_setup(), password_file, client_message, "someperson")
+
+
+def test_envelopes():
+    setup = create_setup("p256_argon2")
+    password_file = password_file_envelope(_register(setup_envelope(setup), suite="p256_argon2"))
+
+    client_finish, server_state, session_key, _ = _login(setup_envelope(setup), password_file, suite="p256_argon2")
+    assert login_finish(client_finish, server_state) == session_key
+
+    # An envelope of the wrong kind is rejected
+    client_message, _ = login_client(password, "p256_argon2")
+    with pytest.raises(ValueError):
+        login(setup, setup_envelope(setup), client_message, "someperson")
+
+
+def test_identities(server_setup: str):
+    ids = {"client_identity": "someperson", "server_identity": "example.com"}
+    password_file = _register(server_setup, **ids)
+
+    client_finish, server_state, session_key, _ = _login(server_setup, password_file, **ids)
+    assert login_finish(client_finish, server_state) == session_key
+
+    login_state, server_message, _ = _login_start(
+        server_setup, password_file, client_identity="someperson", server_identity="example.org"
+    )
+    with pytest.raises(ValueError):
+        login_client_finish(login_state, password, server_message, **ids)
+
+
+def test_context(server_setup: str):
+    password_file = _register(server_setup)
+
+    client_finish, server_state, session_key, _ = _login(server_setup, password_file, context="myapp-v2-login")
+    assert login_finish(client_finish, server_state) == session_key
+
+    login_state, server_message, _ = _login_start(server_setup, password_file, context="otherapp-login")
+    with pytest.raises(ValueError):
+        login_client_finish(login_state, password, server_message, context="myapp-v2-login")
+
+
+def test_unknown_user(server_setup: str):
+    client_message, login_state = login_client(password)
+    server_message, server_state = login(server_setup, None, client_message, "unknownperson")
+    # The response has the length of a real one, 320 bytes for the default suite
+    assert len(server_message) == (320 * 4 + 2) // 3
+    assert server_state
+
+    with pytest.raises(ValueError):
+        login_client_finish(login_state, password, server_message)
+
+
+def test_error_kinds(server_setup: str):
+    with pytest.raises(DecodeError) as e:
+        register(server_setup, "not base64!", "someperson")
+    assert e.value.code == "invalid_base64"
+
+    with pytest.raises(SerializationError):
+        login_finish("AAAA", "AQAA")
+
+    with pytest.raises(WrongObjectKind) as e:
+        login(setup_envelope(server_setup), setup_envelope(server_setup), "AAAA", "someperson")
+    assert e.value.code == "wrong_object_kind"
+
+    with pytest.raises(InvalidParameters):
+        create_ksf_params("argon2id", 1, 1, 1)
+
+    login_state, server_message, _ = _login_start(server_setup, _register(server_setup))
+    with pytest.raises(InvalidLogin) as e:
+        login_client_finish(login_state, "wrong", server_message)
+    assert e.value.code == "invalid_login"
+
+
+def test_exception_hierarchy():
+    for error in [InvalidLogin, DecodeError, SerializationError, InvalidState, InvalidParameters, InternalError]:
+        assert issubclass(error, OpaqueError)
+    assert issubclass(WrongObjectKind, SerializationError)
+    # Handlers written for earlier versions catch ValueError
+    assert issubclass(OpaqueError, ValueError)
+
+    with pytest.raises(OpaqueError):
+        login_finish("AAAA", "AQAA")
+
+
+def register_with_ring(ring: str) -> str:
+    response, client_state = register_client(password)
+    server_response, key_id = register_ring(ring, response, "someperson")
+    return register_finish(register_client_finish(client_state, password, server_response)[0], key_id=key_id)
+
+
+def login_with_ring(ring: str, password_file: str):
+    client_message, login_state = login_client(password)
+    server_message, server_state = login_ring(ring, password_file, client_message, "someperson")
+    client_finish, session_key, _, _ = login_client_finish(login_state, password, server_message)
+    assert login_finish(client_finish, server_state) == session_key
+
+
+def test_setup_ring(server_setup: str):
+    # A password file registered before rings were used
+    legacy_file = _register(server_setup)
+    assert password_file_key_id(legacy_file) is None
+
+    ring = setup_ring_from_setup(server_setup)
+    old_file = register_with_ring(ring)
+    assert password_file_key_id(old_file) == 0
+
+    ring = rotate_setup_ring(ring, 1)
+    new_file = register_with_ring(ring)
+    assert password_file_key_id(new_file) == 1
+
+    for password_file in [legacy_file, old_file, new_file]:
+        login_with_ring(ring, password_file)
+
+    # Password files of a rotated out setup can still be changed, and keep using that setup
+    message, client_state = change_password_client(password, password)
+    server_message, server_state, key_id = change_password_ring(ring, old_file, message, "someperson")
+    assert key_id == 0
+    client_finish, session_key, _, _ = change_password_client_finish(client_state, password, password, server_message)
+    changed_file, server_session_key = change_password_finish(client_finish, server_state, key_id)
+    assert session_key == server_session_key
+    assert password_file_key_id(changed_file) == 0
+    login_with_ring(ring, changed_file)
+
+    with pytest.raises(InvalidState):
+        remove_setup_ring_key(ring, 1)
+    ring = remove_setup_ring_key(ring, 0)
+    with pytest.raises(InvalidParameters):
+        login_with_ring(ring, old_file)
+    login_with_ring(ring, new_file)
+
+
+def test_change_password(server_setup: str):
+    password_file = _register(server_setup)
+
+    new_password = "newpass"
+    ksf_params = create_ksf_params("argon2id", 1024, 1, 1)
+    message, client_state = change_password_client(password, new_password, ksf_params)
+    server_message, server_state = change_password(server_setup, password_file, message, "someperson")
+    client_finish, session_key, export_key, _ = change_password_client_finish(
+        client_state, password, new_password, server_message
+    )
+    seen = set()
+
+    def seen_nonce(nonce: str, expires_at: int) -> bool:
+        new = nonce not in seen
+        seen.add(nonce)
+        return new
+
+    new_password_file, server_session_key = change_password_finish(
+        client_finish, server_state, max_age=60, seen_nonce=seen_nonce
+    )
+    assert session_key == server_session_key
+    with pytest.raises(InvalidState):
+        change_password_finish(client_finish, server_state, max_age=60, seen_nonce=seen_nonce)
+    assert password_file_ksf_params(new_password_file) == ksf_params
+
+    client_finish, server_state, session_key, new_export_key = _login(
+        server_setup, new_password_file, new_password, ksf_params=ksf_params
+    )
+    assert login_finish(client_finish, server_state) == session_key
+    assert new_export_key == export_key
+
+    message, client_state = change_password_client("wrongpass", new_password)
+    server_message, _ = change_password(server_setup, password_file, message, "someperson")
+    with pytest.raises(InvalidLogin):
+        change_password_client_finish(client_state, "wrongpass", new_password, server_message)
+
+
+def test_export_key(server_setup: str):
+    password_file = _register(server_setup)
+
+    export_keys = [_login(server_setup, password_file)[3] for _ in range(2)]
+    # 64 bytes for the default suite
+    assert len(export_keys[0]) == 86
+    assert export_keys[0] == export_keys[1]
+
+
+def test_server_public_key(server_setup: str):
+    response, client_state = register_client(password)
+    server_response = register(server_setup, response, "someperson")
+    client_final_response, registered_key = register_client_finish(client_state, password, server_response)
+    password_file = register_finish(client_final_response)
+    public_key = setup_public_key(server_setup)
+    assert registered_key == public_key
+
+    client_message, login_state = login_client(password)
+    server_message, server_state = login(server_setup, password_file, client_message, "someperson")
+    client_finish, session_key, _, login_key = login_client_finish(
+        login_state, password, server_message, server_public_key=public_key
+    )
+    assert login_key == public_key
+    assert login_finish(client_finish, server_state) == session_key
+
+    login_state, server_message, _ = _login_start(server_setup, password_file)
+    with pytest.raises(InvalidLogin):
+        login_client_finish(
+            login_state, password, server_message, server_public_key=setup_public_key(create_setup())
+        )
+
+
+def test_derive_key_vectors():
+    # The same vectors are used in the Rust tests of `session::derive_key`
+    shared_secret = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0-Pw"
+    assert derive_key(shared_secret, "auth-token", 32) == "2HW-ddl4mXN0csufO1dyEQpqzmVum1NDk3MiT7Qf3xo"
+    assert (
+        derive_key(shared_secret, "channel-encryption", 64)
+        == "Us9qQwAXPmuYL12sokGyV7-rkTbxgm1wwq9E9TyxqOOfaICmIgyAej4ZT2H_9fTbOibftCotw7BXok0N1shj5Q"
+    )
+    assert derive_key(shared_secret, "", 16) == "j1opef4WTToBcgIybGGXrg"
+
+    with pytest.raises(InvalidParameters):
+        derive_key(shared_secret, "auth-token", 16321)
+
+
+def test_derive_key_login(server_setup: str):
+    client_finish, server_state, client_session_key, _ = _login(server_setup, _register(server_setup))
+    server_session_key = login_finish(client_finish, server_state)
+
+    assert derive_key(client_session_key, "auth-token", 32) == derive_key(server_session_key, "auth-token", 32)
+    assert derive_key(client_session_key, "auth-token", 32) != derive_key(client_session_key, "other", 32)
+
+
+def test_session_tag(server_setup: str):
+    shared_secret = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0-Pw"
+    assert session_tag(shared_secret) == "PonaeDioKWA1WA7e0I2RIQ"
+
+    client_finish, server_state, client_session_key, _ = _login(server_setup, _register(server_setup))
+    server_session_key = login_finish(client_finish, server_state)
+    assert session_tag(client_session_key) == session_tag(server_session_key)
+    assert session_tag(client_session_key) != client_session_key
+
+
+def test_verify_session_key():
+    shared_secret = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0-Pw"
+    other = derive_key(shared_secret, "other", 64)
+    assert verify_session_key(shared_secret, shared_secret)
+    assert not verify_session_key(shared_secret, other)
+    assert not verify_session_key(shared_secret, shared_secret[:43])
+    with pytest.raises(DecodeError):
+        verify_session_key(shared_secret, "not base64!")
+
+    assert verify_session_tag(shared_secret, "PonaeDioKWA1WA7e0I2RIQ")
+    assert not verify_session_tag(other, "PonaeDioKWA1WA7e0I2RIQ")
+
+
+# The test vectors of opaque-borink, regenerated with `cargo run --example test_vectors --features test-vectors`
+vectors_file = json.loads((Path(__file__).parent.parent.parent / "opaque-borink" / "test-vectors.json").read_text())
+
+
+@pytest.mark.parametrize("vector", vectors_file["vectors"])
+def test_vectors(vector: dict[str, str]):
+    setup = vector["setup"]
+    assert setup_public_key(setup) == vector["server_public_key"]
+    assert register(setup, vector["register_request"], vector["credential_id"]) == vector["register_response"]
+    password_file = register_finish(vector["register_upload"], vector["suite"])
+    assert password_file == vector["password_file"]
+    assert password_file_ksf_params(password_file) == vector["ksf_params"]
+
+    client_finish, session_key, export_key, server_public_key = login_client_finish(
+        vector["login_client_state"],
+        vector["password"],
+        vector["login_response"],
+        vector["ksf_params"],
+        server_public_key=vector["server_public_key"],
+    )
+    assert client_finish == vector["login_finish"]
+    assert session_key == vector["session_key"]
+    assert export_key == vector["export_key"]
+    assert server_public_key == vector["server_public_key"]
+    assert login_finish(vector["login_finish"], vector["login_server_state"]) == vector["session_key"]
+
+
+def test_sealed_login_state(server_setup: str):
+    key = create_state_key()
+    client_finish, server_state, session_key, _ = _login(server_setup, _register(server_setup))
+    sealed_state = seal_login_state(key, server_state, "someperson")
+
+    with pytest.raises(InvalidLogin):
+        login_finish_sealed(client_finish, sealed_state, key, "otherperson")
+    with pytest.raises(InvalidLogin):
+        login_finish_sealed(client_finish, sealed_state, create_state_key(), "someperson")
+    with pytest.raises(InvalidState):
+        login_finish_sealed(client_finish, seal_login_state(key, server_state, "someperson", 0), key, "someperson")
+    assert login_finish_sealed(client_finish, sealed_state, key, "someperson") == session_key
+
+
+def test_login_state_replay(server_setup: str):
+    client_finish, server_state, session_key, _ = _login(server_setup, _register(server_setup))
+
+    seen = {}
+
+    def seen_nonce(nonce: str, expires_at: int) -> bool:
+        if nonce in seen:
+            return False
+        seen[nonce] = expires_at
+        return True
+
+    assert login_finish(client_finish, server_state, max_age=60, seen_nonce=seen_nonce) == session_key
+    assert len(seen) == 1
+    with pytest.raises(InvalidState):
+        login_finish(client_finish, server_state, max_age=60, seen_nonce=seen_nonce)
+    # The nonces could never be forgotten
+    with pytest.raises(InvalidState):
+        login_finish(client_finish, server_state, seen_nonce=seen_nonce)
+
+    def failing_store(nonce: str, expires_at: int) -> bool:
+        raise ConnectionError("store unavailable")
+
+    with pytest.raises(InternalError):
+        login_finish(client_finish, server_state, max_age=60, seen_nonce=failing_store)
+
+
+def test_async(server_setup: str):
+    async def flow() -> None:
+        response, client_state = register_client(password)
+        server_response = await register_async(server_setup, response, "someperson")
+        client_finish, _ = await register_client_finish_async(client_state, password, server_response)
+        password_file = register_finish(client_finish)
+
+        # Logins run concurrently in the thread pool
+        starts = [login_client(password) for _ in range(3)]
+        server_starts = await asyncio.gather(
+            *(login_async(server_setup, password_file, message, "someperson") for message, _ in starts)
+        )
+        finishes = await asyncio.gather(
+            *(
+                login_client_finish_async(state, password, server_message)
+                for (_, state), (server_message, _) in zip(starts, server_starts)
+            )
+        )
+        for (client_finish, session_key, _, _), (_, server_state) in zip(finishes, server_starts):
+            assert login_finish(client_finish, server_state) == session_key
+
+    asyncio.run(flow())
@@ -0,0 +1,94 @@
+// Promise-based versions of the client steps using Argon2, which run them in a Web Worker so that
+// they do not block the main thread. The states are sent to the worker in their serialized form.
+//
+//   const worker = new OpaqueWorker();
+//   const state = client_login_wasm(password);
+//   // send state.message to the server...
+//   const result = await worker.clientLoginFinish(state, password, serverMessage);
+
+export class OpaqueWorker {
+    /**
+     * @param {Worker} [worker] A worker running `worker.js`, by default one is started from the
+     * same directory as this file.
+     */
+    constructor(worker) {
+        this.worker = worker ?? new Worker(new URL("./worker.js", import.meta.url), { type: "module" });
+        this.nextId = 0;
+        this.pending = new Map();
+        this.worker.onmessage = (event) => {
+            const { id, result, error } = event.data;
+            const request = this.pending.get(id);
+            // Unknown ids are replies to requests that were already rejected by `rejectAll`
+            if (!request) {
+                return;
+            }
+            const { resolve, reject } = request;
+            this.pending.delete(id);
+            if (error) {
+                const err = new Error(error.message);
+                err.name = error.name;
+                err.code = error.code;
+                reject(err);
+            } else {
+                resolve(result);
+            }
+        };
+        // A worker that fails to load or crashes never replies, and a reply that can't be
+        // deserialized has no id, so all pending requests are rejected
+        this.worker.onerror = (event) => {
+            this.rejectAll(new Error(event.message || "the worker failed"));
+        };
+        this.worker.onmessageerror = () => {
+            this.rejectAll(new Error("a message from the worker could not be deserialized"));
+        };
+    }
+
+    rejectAll(err) {
+        for (const { reject } of this.pending.values()) {
+            reject(err);
+        }
+        this.pending.clear();
+    }
+
+    request(op, args) {
+        const id = this.nextId++;
+        return new Promise((resolve, reject) => {
+            this.pending.set(id, { resolve, reject });
+            this.worker.postMessage({ id, op, args });
+        });
+    }
+
+    /**
+     * Like `client_register_finish_wasm`, resolving to an object with the same fields as
+     * `ClientRegistrationResult`.
+     */
+    clientRegisterFinish(state, password, serverMessage, clientIdentity, serverIdentity) {
+        return this.request("client_register_finish", [
+            state.serialize(), password, serverMessage, clientIdentity, serverIdentity,
+        ]);
+    }
+
+    /**
+     * Like `client_login_finish_wasm`, resolving to an object with the same fields as
+     * `ClientLoginResult`.
+     */
+    clientLoginFinish(state, password, serverMessage, ksfParams, clientIdentity, serverIdentity, context, serverPublicKey) {
+        return this.request("client_login_finish", [
+            state.serialize(), password, serverMessage, ksfParams, clientIdentity, serverIdentity, context, serverPublicKey,
+        ]);
+    }
+
+    /**
+     * Like `client_change_password_finish_wasm`, resolving to an object with the same fields as
+     * `ClientChangePasswordResult`.
+     */
+    clientChangePasswordFinish(state, oldPassword, newPassword, serverMessage, ksfParams, clientIdentity, serverIdentity, context, serverPublicKey) {
+        return this.request("client_change_password_finish", [
+            state.serialize(), oldPassword, newPassword, serverMessage, ksfParams, clientIdentity, serverIdentity, context, serverPublicKey,
+        ]);
+    }
+
+    terminate() {
+        this.worker.terminate();
+    }
+}
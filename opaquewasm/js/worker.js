@@ -0,0 +1,17 @@
+// Web Worker entry point, which runs the client steps using Argon2 off the main thread. Copy it
+// into `pkg` next to `opaquewasm.js` and start it through `OpaqueWorker` in `opaque-worker.js`.
+import init, { handle_worker_request_wasm } from "./opaquewasm.js";
+
+const ready = init();
+
+self.onmessage = async (event) => {
+    const { id, op, args } = event.data;
+    try {
+        await ready;
+        const result = handle_worker_request_wasm({ op, args });
+        self.postMessage({ id, result });
+    } catch (e) {
+        // The name and code of errors are lost when they are cloned, so they are sent separately
+        self.postMessage({ id, error: { name: e.name, code: e.code, message: e.message } });
+    }
+};
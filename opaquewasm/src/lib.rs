@@ -1,16 +1,22 @@
-use opaque_borink::client::{
-    client_login_finish, client_login_start, client_register_finish, client_register_start
+use opaque_borink::client::{LOGIN_CLIENT_STATE_LEN, REGISTER_CLIENT_STATE_LEN};
+use opaque_borink::typed::{
+    client_change_password_finish, client_change_password_start, client_login_finish, client_login_start,
+    client_register_finish, client_register_start, ChangePasswordStartResponse, LoginStartResponse, RegistrationResponseBytes
 };
-use opaque_borink::encoded::{decode_string, encode_bytes};
-use opaque_borink::{Error, ProtocolError};
-use std::fmt::Debug;
+use opaque_borink::encoded::{decode_secret, decode_string, encode_bytes};
+use opaque_borink::secret::SecretBytes;
+use opaque_borink::ksf::{Algorithm, KsfParams};
+use opaque_borink::session::{derive_key, session_tag, verify_session_key, verify_session_tag};
+use opaque_borink::suite::SuiteId;
+use opaque_borink::{with_suite, Error, Identifiers};
 use wasm_bindgen::prelude::*;
 
-pub type OpaqueJsResult<T> = Result<T, JsValue>;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "server")]
+pub use server::*;
 
-fn make_js_val<T: Debug>(val: T, info: &str) -> JsValue {
-    JsValue::from(format!("{:?} {}", val, info))
-}
+pub type OpaqueJsResult<T> = Result<T, JsValue>;
 
 pub struct OpaqueJsError(Error);
 
@@ -20,30 +26,44 @@ impl From<Error> for OpaqueJsError {
     }
 }
 
-/// Create an InvalidLogin error for JavaScript
-fn invalid_login(info: &str) -> JsValue {
-    let err = js_sys::Error::new(info);
-    err.set_name("InvalidLogin");
-    JsValue::from(err)
-}
-
+/// Errors are converted to a JavaScript `Error` named after the `ErrorKind` (e.g. "InvalidLogin"),
+/// with a `code` property holding the stable error code.
 impl From<OpaqueJsError> for JsValue {
     fn from(e: OpaqueJsError) -> Self {
-        let info = "default error";
-        match e.0 {
-            Error::ProtocolError(oe) => match oe {
-                ProtocolError::InvalidLoginError => invalid_login(info),
-                _ => make_js_val(oe, info),
-            },
-            Error::DecodeError(oe) => JsValue::from(format!("{} {}", oe, info)),
-        }
+        let kind = e.0.kind();
+        let err = js_sys::Error::new(&e.0.to_string());
+        err.set_name(kind.name());
+        // Setting a property on a fresh object does not fail
+        let _ = js_sys::Reflect::set(&err, &JsValue::from_str("code"), &JsValue::from_str(kind.code()));
+        JsValue::from(err)
+    }
+}
+
+fn decode_ksf_params(ksf_params: Option<String>) -> Result<KsfParams, OpaqueJsError> {
+    match ksf_params {
+        Some(ksf_params) => Ok(KsfParams::deserialize(&decode_string(&ksf_params)?)?),
+        None => Ok(KsfParams::default()),
     }
 }
 
+fn parse_suite(suite: Option<String>) -> Result<SuiteId, OpaqueJsError> {
+    match suite {
+        Some(suite) => Ok(suite.parse()?),
+        None => Ok(SuiteId::default()),
+    }
+}
+
+fn identifiers<'a>(client_identity: &'a Option<String>, server_identity: &'a Option<String>) -> Identifiers<'a> {
+    Identifiers {
+        client: client_identity.as_deref().map(str::as_bytes),
+        server: server_identity.as_deref().map(str::as_bytes),
+    }
+}
 
+/// The states are kept in their serialized form, as they carry the tag of the suite they belong to.
 #[wasm_bindgen]
 pub struct ClientStateRegistration {
-    state: opaque_borink::client::ClientStateRegistration,
+    state: SecretBytes,
     message: String
 }
 
@@ -53,18 +73,52 @@ impl ClientStateRegistration {
     pub fn message(&self) -> String {
         self.message.to_owned()
     }
+
+    /// The state encoded as base64url, for sending it to a worker. Restore it using `deserialize`.
+    pub fn serialize(&self) -> String {
+        encode_bytes(&self.state)
+    }
+
+    pub fn deserialize(state: &str, message: &str) -> OpaqueJsResult<ClientStateRegistration> {
+        let state = decode_secret(state).map_err(OpaqueJsError)?;
+        SuiteId::from_serialized(&state, REGISTER_CLIENT_STATE_LEN).map_err(OpaqueJsError)?;
+
+        Ok(ClientStateRegistration { state, message: message.to_owned() })
+    }
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct ClientRegistrationResult {
+    pub message: String,
+    /// Static public key of the server, for pinning using `server_public_key` of
+    /// `client_login_finish_wasm`.
+    pub server_public_key: String
 }
 
 #[wasm_bindgen]
 pub struct ClientStateLogin {
-    state: opaque_borink::client::ClientStateLogin,
+    state: SecretBytes,
     message: String
 }
 
 #[wasm_bindgen(getter_with_clone)]
 pub struct ClientLoginResult {
     pub message: String,
-    pub shared_secret: String
+    pub shared_secret: String,
+    /// Only known to the client and the same for every login with the same registration, for
+    /// encrypting data client-side.
+    pub export_key: String,
+    /// Static public key of the server, for pinning using `server_public_key` of
+    /// `client_login_finish_wasm`.
+    pub server_public_key: String
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct ClientChangePasswordResult {
+    pub message: String,
+    pub shared_secret: String,
+    pub export_key: String,
+    pub old_export_key: String
 }
 
 #[wasm_bindgen]
@@ -73,65 +127,326 @@ impl ClientStateLogin {
     pub fn message(&self) -> String {
         self.message.to_owned()
     }
+
+    /// The state encoded as base64url, for sending it to a worker. Restore it using `deserialize`.
+    pub fn serialize(&self) -> String {
+        encode_bytes(&self.state)
+    }
+
+    pub fn deserialize(state: &str, message: &str) -> OpaqueJsResult<ClientStateLogin> {
+        let state = decode_secret(state).map_err(OpaqueJsError)?;
+        SuiteId::from_serialized(&state, LOGIN_CLIENT_STATE_LEN).map_err(OpaqueJsError)?;
+
+        Ok(ClientStateLogin { state, message: message.to_owned() })
+    }
+}
+
+#[wasm_bindgen]
+pub struct ClientStateChangePassword {
+    state: SecretBytes,
+    message: String
+}
+
+#[wasm_bindgen]
+impl ClientStateChangePassword {
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.to_owned()
+    }
+
+    /// The state encoded as base64url, for sending it to a worker. Restore it using `deserialize`.
+    pub fn serialize(&self) -> String {
+        encode_bytes(&self.state)
+    }
+
+    pub fn deserialize(state: &str, message: &str) -> OpaqueJsResult<ClientStateChangePassword> {
+        let state = decode_secret(state).map_err(OpaqueJsError)?;
+        // The state has no untagged legacy form
+        SuiteId::from_serialized(&state, usize::MAX).map_err(OpaqueJsError)?;
+
+        Ok(ClientStateChangePassword { state, message: message.to_owned() })
+    }
+}
+
+/// Derives a key of `len` bytes from the base64url-encoded `shared_secret` of a login, using
+/// HKDF-SHA512 with the `label` as info. Matches `derive_key` in opaquepy.
+#[wasm_bindgen]
+pub fn derive_key_wasm(shared_secret: &str, label: &str, len: usize) -> OpaqueJsResult<String> {
+    let shared_secret = decode_secret(shared_secret).map_err(OpaqueJsError)?;
+    let key = derive_key(&shared_secret, label.as_bytes(), len)
+    .map_err(OpaqueJsError)?;
+
+    Ok(encode_bytes(&key))
+}
+
+/// Short identifier of the session, computed from the base64url-encoded `shared_secret` of a login.
+/// It does not reveal the shared secret, so it can be used as a session handle. Matches
+/// `session_tag` in opaquepy.
+#[wasm_bindgen]
+pub fn session_tag_wasm(shared_secret: &str) -> OpaqueJsResult<String> {
+    let shared_secret = decode_secret(shared_secret).map_err(OpaqueJsError)?;
+
+    Ok(encode_bytes(&session_tag(&shared_secret)))
 }
 
+/// Compares two base64url-encoded shared secrets or derived keys in constant time, unlike `===`
+/// on the strings. Matches `verify_session_key` in opaquepy.
 #[wasm_bindgen]
-pub fn client_register_wasm(password: &str) -> OpaqueJsResult<ClientStateRegistration> {
-    let mut state = opaque_borink::client::ClientStateRegistration::setup();
+pub fn verify_session_key_wasm(a: &str, b: &str) -> OpaqueJsResult<bool> {
+    let a = decode_secret(a).map_err(OpaqueJsError)?;
+    let b = decode_secret(b).map_err(OpaqueJsError)?;
+
+    Ok(verify_session_key(&a, &b))
+}
+
+/// Whether the base64url-encoded `tag` is the session tag of the `shared_secret`, compared in
+/// constant time. Matches `verify_session_tag` in opaquepy.
+#[wasm_bindgen]
+pub fn verify_session_tag_wasm(shared_secret: &str, tag: &str) -> OpaqueJsResult<bool> {
+    let shared_secret = decode_secret(shared_secret).map_err(OpaqueJsError)?;
+    let tag = decode_string(tag).map_err(OpaqueJsError)?;
+
+    Ok(verify_session_tag(&shared_secret, &tag))
+}
 
-    let result = client_register_start(&mut state, password.as_bytes())
+#[wasm_bindgen]
+pub fn create_ksf_params_wasm(
+    algorithm: &str,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+) -> OpaqueJsResult<String> {
+    let algorithm = Algorithm::new(algorithm)
+    .map_err(|e| OpaqueJsError(e.into()))?;
+    let ksf_params = KsfParams::new(algorithm, m_cost, t_cost, p_cost)
     .map_err(OpaqueJsError)?;
 
+    Ok(encode_bytes(&ksf_params.serialize()))
+}
+
+#[wasm_bindgen]
+pub fn client_register_wasm(password: &str, ksf_params: Option<String>, suite: Option<String>) -> OpaqueJsResult<ClientStateRegistration> {
+    let ksf_params = decode_ksf_params(ksf_params)?;
+    let suite = parse_suite(suite)?;
+
+    let (result, state) = with_suite!(suite, CS => {
+        let mut state = opaque_borink::client::ClientStateRegistration::<CS>::setup();
+        let result = client_register_start(&mut state, password.as_bytes(), ksf_params)
+        .map_err(OpaqueJsError)?;
+        (result, state.serialize().map_err(OpaqueJsError)?)
+    });
+
     Ok(ClientStateRegistration {
-        message: encode_bytes(&result.response),
+        message: result.response.to_base64(),
         state
     })
 }
 
 #[wasm_bindgen]
 pub fn client_register_finish_wasm(
-    mut client_register_state: ClientStateRegistration,
+    client_register_state: ClientStateRegistration,
     password: &str,
     server_message: &str,
-) -> OpaqueJsResult<String> {
-    let server_message = decode_string(server_message).map_err(OpaqueJsError)?;
-
-    let result = client_register_finish(&mut client_register_state.state, password.as_bytes(), &server_message)
+    client_identity: Option<String>,
+    server_identity: Option<String>,
+) -> OpaqueJsResult<ClientRegistrationResult> {
+    let server_message = RegistrationResponseBytes::from_base64(server_message).map_err(OpaqueJsError)?;
+    let suite = SuiteId::from_serialized(&client_register_state.state, REGISTER_CLIENT_STATE_LEN)
     .map_err(OpaqueJsError)?;
 
+    let result = with_suite!(suite, CS => {
+        let mut state = opaque_borink::client::ClientStateRegistration::<CS>::deserialize(&client_register_state.state)
+        .map_err(OpaqueJsError)?;
+        client_register_finish(&mut state, password.as_bytes(), &server_message, identifiers(&client_identity, &server_identity))
+        .map_err(OpaqueJsError)?
+    });
+
     Ok(
-        encode_bytes(&result.response)
+        ClientRegistrationResult {
+            message: result.response.to_base64(),
+            server_public_key: encode_bytes(&result.server_public_key)
+        }
     )
 }
 
 #[wasm_bindgen]
-pub fn client_login_wasm(password: &str) -> OpaqueJsResult<ClientStateLogin> {
-    let mut state = opaque_borink::client::ClientStateLogin::setup();
+pub fn client_login_wasm(password: &str, suite: Option<String>) -> OpaqueJsResult<ClientStateLogin> {
+    let suite = parse_suite(suite)?;
 
-    let result = client_login_start(&mut state, password.as_bytes())
-    .map_err(OpaqueJsError)?;
+    let (result, state) = with_suite!(suite, CS => {
+        let mut state = opaque_borink::client::ClientStateLogin::<CS>::setup();
+        let result = client_login_start(&mut state, password.as_bytes())
+        .map_err(OpaqueJsError)?;
+        (result, state.serialize().map_err(OpaqueJsError)?)
+    });
 
     Ok(ClientStateLogin {
         state,
-        message: encode_bytes(&result.response)
+        message: result.response.to_base64()
     })
 }
 
+/// When `server_public_key` is given, the login fails with an `InvalidLogin` error if the server
+/// uses a different public key.
 #[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
 pub fn client_login_finish_wasm(
-    mut client_login_state: ClientStateLogin,
+    client_login_state: ClientStateLogin,
     password: &str,
     server_message: &str,
+    ksf_params: Option<String>,
+    client_identity: Option<String>,
+    server_identity: Option<String>,
+    context: Option<String>,
+    server_public_key: Option<String>,
 ) -> OpaqueJsResult<ClientLoginResult> {
-    let server_message = decode_string(server_message).map_err(OpaqueJsError)?;
-
-    let result = client_login_finish(&mut client_login_state.state, password.as_bytes(), &server_message)
+    let server_message = LoginStartResponse::from_base64(server_message).map_err(OpaqueJsError)?;
+    let ksf_params = decode_ksf_params(ksf_params)?;
+    let server_public_key = server_public_key.as_deref().map(decode_string).transpose().map_err(OpaqueJsError)?;
+    let suite = SuiteId::from_serialized(&client_login_state.state, LOGIN_CLIENT_STATE_LEN)
     .map_err(OpaqueJsError)?;
 
+    let result = with_suite!(suite, CS => {
+        let mut state = opaque_borink::client::ClientStateLogin::<CS>::deserialize(&client_login_state.state)
+        .map_err(OpaqueJsError)?;
+        client_login_finish(&mut state, password.as_bytes(), &server_message, ksf_params, identifiers(&client_identity, &server_identity), context.as_deref().map(str::as_bytes), server_public_key.as_deref())
+        .map_err(OpaqueJsError)?
+    });
+
     Ok(
         ClientLoginResult {
-            message: encode_bytes(&result.response),
-            shared_secret: encode_bytes(&result.shared_secret)
+            message: result.response.to_base64(),
+            shared_secret: encode_bytes(&result.shared_secret),
+            export_key: encode_bytes(&result.export_key),
+            server_public_key: encode_bytes(&result.server_public_key)
         }        
     )
 }
+
+/// The `ksf_params` are used for the new password.
+#[wasm_bindgen]
+pub fn client_change_password_wasm(
+    old_password: &str,
+    new_password: &str,
+    ksf_params: Option<String>,
+    suite: Option<String>,
+) -> OpaqueJsResult<ClientStateChangePassword> {
+    let ksf_params = decode_ksf_params(ksf_params)?;
+    let suite = parse_suite(suite)?;
+
+    let (result, state) = with_suite!(suite, CS => {
+        let mut state = opaque_borink::client::ClientStateChangePassword::<CS>::setup();
+        let result = client_change_password_start(&mut state, old_password.as_bytes(), new_password.as_bytes(), ksf_params)
+        .map_err(OpaqueJsError)?;
+        (result, state.serialize().map_err(OpaqueJsError)?)
+    });
+
+    Ok(ClientStateChangePassword {
+        state,
+        message: result.response.to_base64()
+    })
+}
+
+/// The `ksf_params` are those of the current password file. The `server_public_key` is checked as
+/// in `client_login_finish_wasm`.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn client_change_password_finish_wasm(
+    client_change_password_state: ClientStateChangePassword,
+    old_password: &str,
+    new_password: &str,
+    server_message: &str,
+    ksf_params: Option<String>,
+    client_identity: Option<String>,
+    server_identity: Option<String>,
+    context: Option<String>,
+    server_public_key: Option<String>,
+) -> OpaqueJsResult<ClientChangePasswordResult> {
+    let server_message = ChangePasswordStartResponse::from_base64(server_message).map_err(OpaqueJsError)?;
+    let ksf_params = decode_ksf_params(ksf_params)?;
+    let server_public_key = server_public_key.as_deref().map(decode_string).transpose().map_err(OpaqueJsError)?;
+    // The state has no untagged legacy form
+    let suite = SuiteId::from_serialized(&client_change_password_state.state, usize::MAX)
+    .map_err(OpaqueJsError)?;
+
+    let result = with_suite!(suite, CS => {
+        let mut state = opaque_borink::client::ClientStateChangePassword::<CS>::deserialize(&client_change_password_state.state)
+        .map_err(OpaqueJsError)?;
+        client_change_password_finish(&mut state, old_password.as_bytes(), new_password.as_bytes(), &server_message, ksf_params, identifiers(&client_identity, &server_identity), context.as_deref().map(str::as_bytes), server_public_key.as_deref())
+        .map_err(OpaqueJsError)?
+    });
+
+    Ok(
+        ClientChangePasswordResult {
+            message: result.response.to_base64(),
+            shared_secret: encode_bytes(&result.shared_secret),
+            export_key: encode_bytes(&result.export_key),
+            old_export_key: encode_bytes(&result.old_export_key)
+        }
+    )
+}
+
+fn request_arg(args: &js_sys::Array, index: u32) -> OpaqueJsResult<String> {
+    args.get(index).as_string().ok_or_else(|| js_sys::TypeError::new(&format!("argument {} must be a string", index)).into())
+}
+
+/// Missing, `null` and `undefined` arguments are `None`.
+fn optional_request_arg(args: &js_sys::Array, index: u32) -> Option<String> {
+    args.get(index).as_string()
+}
+
+fn plain_object(entries: &[(&str, String)]) -> JsValue {
+    let object = js_sys::Object::new();
+    for (key, value) in entries {
+        // Setting a property on a fresh object does not fail
+        let _ = js_sys::Reflect::set(&object, &JsValue::from_str(key), &JsValue::from_str(value));
+    }
+    JsValue::from(object)
+}
+
+/// Entry point of the Web Worker in `js/worker.js`, which runs the steps using Argon2 off the main
+/// thread. The `request` is an object `{ op, args }`, where `op` is one of
+/// "client_register_finish", "client_login_finish" or "client_change_password_finish" and `args`
+/// the arguments of the `_wasm` function, with the state passed as its `serialize` form. Results
+/// are plain objects, as the result classes can't be sent between threads.
+#[wasm_bindgen]
+pub fn handle_worker_request_wasm(request: JsValue) -> OpaqueJsResult<JsValue> {
+    let op = js_sys::Reflect::get(&request, &JsValue::from_str("op"))?.as_string()
+    .ok_or_else(|| js_sys::TypeError::new("the request has no op"))?;
+    let args = js_sys::Array::from(&js_sys::Reflect::get(&request, &JsValue::from_str("args"))?);
+
+    match op.as_str() {
+        "client_register_finish" => {
+            let state = ClientStateRegistration::deserialize(&request_arg(&args, 0)?, "")?;
+            let result = client_register_finish_wasm(state, &request_arg(&args, 1)?, &request_arg(&args, 2)?, optional_request_arg(&args, 3), optional_request_arg(&args, 4))?;
+
+            Ok(plain_object(&[
+                ("message", result.message),
+                ("server_public_key", result.server_public_key),
+            ]))
+        }
+        "client_login_finish" => {
+            let state = ClientStateLogin::deserialize(&request_arg(&args, 0)?, "")?;
+            let result = client_login_finish_wasm(state, &request_arg(&args, 1)?, &request_arg(&args, 2)?, optional_request_arg(&args, 3), optional_request_arg(&args, 4), optional_request_arg(&args, 5), optional_request_arg(&args, 6), optional_request_arg(&args, 7))?;
+
+            Ok(plain_object(&[
+                ("message", result.message),
+                ("shared_secret", result.shared_secret),
+                ("export_key", result.export_key),
+                ("server_public_key", result.server_public_key),
+            ]))
+        }
+        "client_change_password_finish" => {
+            let state = ClientStateChangePassword::deserialize(&request_arg(&args, 0)?, "")?;
+            let result = client_change_password_finish_wasm(state, &request_arg(&args, 1)?, &request_arg(&args, 2)?, &request_arg(&args, 3)?, optional_request_arg(&args, 4), optional_request_arg(&args, 5), optional_request_arg(&args, 6), optional_request_arg(&args, 7), optional_request_arg(&args, 8))?;
+
+            Ok(plain_object(&[
+                ("message", result.message),
+                ("shared_secret", result.shared_secret),
+                ("export_key", result.export_key),
+                ("old_export_key", result.old_export_key),
+            ]))
+        }
+        _ => Err(js_sys::TypeError::new(&format!("unknown op {}", op)).into()),
+    }
+}
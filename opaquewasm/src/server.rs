@@ -0,0 +1,170 @@
+//! Server side of the protocol, for backends running on Node, Deno or edge runtimes. Only built with
+//! the `server` feature, so that it is not part of the browser bundle.
+
+use std::time::Duration;
+
+use opaque_borink::server::{PasswordFile, ServerSetup, LOGIN_SERVER_STATE_LEN, PASSWORD_FILE_REGISTRATION_LEN, SERVER_SETUP_LEN};
+use opaque_borink::typed::{
+    server_login_finish, server_login_start, server_register_finish, server_register_start,
+    LoginFinishRequest, LoginServerState, LoginStartRequest, RegistrationRequestBytes, RegistrationUploadBytes
+};
+use opaque_borink::encoded::{decode_secret, decode_string, encode_bytes};
+use opaque_borink::replay::{SeenNonces, StateChecks, STATE_NONCE_LEN};
+use opaque_borink::session::session_tag;
+use opaque_borink::suite::SuiteId;
+use opaque_borink::{with_suite, Error};
+use wasm_bindgen::prelude::*;
+
+use crate::{identifiers, parse_suite, OpaqueJsError, OpaqueJsResult};
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct ServerLoginResult {
+    pub message: String,
+    /// Login state to keep on the server until `server_login_finish_wasm`. It is secret, so it
+    /// must not be sent to the client.
+    pub state: String
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct ServerLoginFinishResult {
+    pub shared_secret: String,
+    /// Identifier of the session that does not reveal the shared secret, see `session_tag_wasm`.
+    pub session_tag: String
+}
+
+/// Generates a server setup, which is used for validating password files. The other functions
+/// determine the cipher suite from the setup, password file or state they are given.
+#[wasm_bindgen]
+pub fn create_setup_wasm(suite: Option<String>) -> OpaqueJsResult<String> {
+    let suite = parse_suite(suite)?;
+
+    Ok(with_suite!(suite, CS => encode_bytes(&ServerSetup::<CS>::create().serialize())))
+}
+
+/// The public key of the `setup`, for pinning using `server_public_key` of
+/// `client_login_finish_wasm`.
+#[wasm_bindgen]
+pub fn setup_public_key_wasm(setup: &str) -> OpaqueJsResult<String> {
+    let setup = decode_secret(setup).map_err(OpaqueJsError)?;
+    let suite = SuiteId::from_serialized(&setup, SERVER_SETUP_LEN).map_err(OpaqueJsError)?;
+
+    let public_key = with_suite!(suite, CS => ServerSetup::<CS>::deserialize(&setup).map_err(OpaqueJsError)?.public_key());
+
+    Ok(encode_bytes(&public_key))
+}
+
+#[wasm_bindgen]
+pub fn server_register_wasm(setup: &str, client_request: &str, credential_id: &str) -> OpaqueJsResult<String> {
+    let setup = decode_secret(setup).map_err(OpaqueJsError)?;
+    let suite = SuiteId::from_serialized(&setup, SERVER_SETUP_LEN).map_err(OpaqueJsError)?;
+    let client_request = RegistrationRequestBytes::from_base64(client_request).map_err(OpaqueJsError)?;
+
+    let result = with_suite!(suite, CS => {
+        let setup = ServerSetup::<CS>::deserialize(&setup)
+        .map_err(OpaqueJsError)?;
+        server_register_start(&mut setup.view(), &client_request, credential_id.as_bytes())
+        .map_err(OpaqueJsError)?
+    });
+
+    Ok(result.response.to_base64())
+}
+
+/// Returns the password file to be saved. The `setup` is the one the registration was started with,
+/// which determines the cipher suite.
+#[wasm_bindgen]
+pub fn server_register_finish_wasm(setup: &str, client_request_finish: &str) -> OpaqueJsResult<String> {
+    let setup = decode_secret(setup).map_err(OpaqueJsError)?;
+    let suite = SuiteId::from_serialized(&setup, SERVER_SETUP_LEN).map_err(OpaqueJsError)?;
+    let client_request_finish = RegistrationUploadBytes::from_base64(client_request_finish).map_err(OpaqueJsError)?;
+
+    let password_file = with_suite!(suite, CS => {
+        server_register_finish::<CS>(&client_request_finish)
+        .map_err(OpaqueJsError)?
+        .serialize()
+    });
+
+    Ok(encode_bytes(&password_file))
+}
+
+/// The KSF parameters the client registered with, which must be passed to
+/// `client_login_finish_wasm`.
+#[wasm_bindgen]
+pub fn password_file_ksf_params_wasm(password_file: &str) -> OpaqueJsResult<String> {
+    let password_file = decode_string(password_file).map_err(OpaqueJsError)?;
+    let suite = SuiteId::from_serialized(&password_file, PASSWORD_FILE_REGISTRATION_LEN).map_err(OpaqueJsError)?;
+
+    let ksf_params = with_suite!(suite, CS => PasswordFile::<CS>::deserialize(&password_file).map_err(OpaqueJsError)?.ksf_params());
+
+    Ok(encode_bytes(&ksf_params.serialize()))
+}
+
+/// Pass `undefined` as the `password_file` for users that do not exist. A fake response is then
+/// returned, so that the login only fails at the finish step without revealing whether the account
+/// exists. The identities and `context` must match those used by the client.
+#[wasm_bindgen]
+pub fn server_login_wasm(
+    setup: &str,
+    password_file: Option<String>,
+    client_request: &str,
+    credential_id: &str,
+    client_identity: Option<String>,
+    server_identity: Option<String>,
+    context: Option<String>,
+) -> OpaqueJsResult<ServerLoginResult> {
+    let setup = decode_secret(setup).map_err(OpaqueJsError)?;
+    let suite = SuiteId::from_serialized(&setup, SERVER_SETUP_LEN).map_err(OpaqueJsError)?;
+    let password_file = password_file.as_deref().map(decode_string).transpose().map_err(OpaqueJsError)?;
+    let client_request = LoginStartRequest::from_base64(client_request).map_err(OpaqueJsError)?;
+
+    let result = with_suite!(suite, CS => {
+        let setup = ServerSetup::<CS>::deserialize(&setup)
+        .map_err(OpaqueJsError)?;
+        let password_file = password_file.map(|password_file| PasswordFile::<CS>::deserialize(&password_file)).transpose()
+        .map_err(OpaqueJsError)?;
+        server_login_start(&mut setup.view(), password_file.as_ref(), &client_request, credential_id, identifiers(&client_identity, &server_identity), context.as_deref().map(str::as_bytes))
+        .map_err(OpaqueJsError)?
+    });
+
+    Ok(
+        ServerLoginResult {
+            message: result.response.to_base64(),
+            state: result.state.to_base64()
+        }
+    )
+}
+
+/// Calls a JS `seenNonce(nonce, expiresAt)` function, which returns whether the encoded nonce is
+/// new.
+struct JsSeenNonces<'a>(&'a js_sys::Function);
+
+impl SeenNonces for JsSeenNonces<'_> {
+    fn insert(&self, nonce: &[u8; STATE_NONCE_LEN], expires_at: u64) -> Result<bool, Error> {
+        self.0.call2(&JsValue::NULL, &encode_bytes(nonce).into(), &(expires_at as f64).into())
+        .map(|new| new.is_truthy())
+        .map_err(|e| Error::NonceStore(e.dyn_ref::<js_sys::Error>().map(|e| e.message().into()).or_else(|| e.as_string()).unwrap_or_default()))
+    }
+}
+
+/// Fails with an `InvalidState` error if the login state is older than `max_age` seconds, or if
+/// `seen_nonce` reports it as used before. `seen_nonce` is called with the encoded nonce of the
+/// state and the unix time until which it must be remembered, and must synchronously return
+/// whether the nonce is new. It requires a `max_age`, as the nonces could never be forgotten
+/// otherwise.
+#[wasm_bindgen]
+pub fn server_login_finish_wasm(client_request_finish: &str, login_state: &str, max_age: Option<u32>, seen_nonce: Option<js_sys::Function>) -> OpaqueJsResult<ServerLoginFinishResult> {
+    let client_request_finish = LoginFinishRequest::from_base64(client_request_finish).map_err(OpaqueJsError)?;
+    let login_state = LoginServerState::from_base64(login_state).map_err(OpaqueJsError)?;
+    let suite = SuiteId::from_serialized(login_state.as_bytes(), LOGIN_SERVER_STATE_LEN).map_err(OpaqueJsError)?;
+    let seen_nonce = seen_nonce.as_ref().map(JsSeenNonces);
+    let checks = StateChecks { max_age: max_age.map(|max_age| Duration::from_secs(max_age.into())), seen_nonces: seen_nonce.as_ref().map(|s| s as &dyn SeenNonces) };
+
+    let result = with_suite!(suite, CS => server_login_finish::<CS>(&client_request_finish, &login_state, &checks)
+    .map_err(OpaqueJsError)?);
+
+    Ok(
+        ServerLoginFinishResult {
+            shared_secret: encode_bytes(&result.shared_secret),
+            session_tag: encode_bytes(&session_tag(&result.shared_secret))
+        }
+    )
+}
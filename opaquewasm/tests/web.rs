@@ -3,6 +3,11 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use opaquewasm::{
+    client_login_finish_wasm, client_login_wasm, derive_key_wasm, handle_worker_request_wasm, session_tag_wasm,
+    verify_session_key_wasm, verify_session_tag_wasm, ClientStateLogin,
+};
+use wasm_bindgen::JsValue;
 use wasm_bindgen_test::*;
 
 wasm_bindgen_test_configure!(run_in_browser);
@@ -11,3 +16,151 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+/// The same vectors are used in the Rust tests of `session::derive_key` and in opaquepy.
+#[wasm_bindgen_test]
+fn derive_key_vectors() {
+    let shared_secret = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0-Pw";
+
+    assert_eq!(
+        "2HW-ddl4mXN0csufO1dyEQpqzmVum1NDk3MiT7Qf3xo",
+        derive_key_wasm(shared_secret, "auth-token", 32).unwrap()
+    );
+    assert_eq!(
+        "j1opef4WTToBcgIybGGXrg",
+        derive_key_wasm(shared_secret, "", 16).unwrap()
+    );
+    assert!(derive_key_wasm(shared_secret, "auth-token", 16321).is_err());
+}
+
+#[wasm_bindgen_test]
+fn session_tag_vectors() {
+    let shared_secret = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0-Pw";
+
+    assert_eq!(
+        "PonaeDioKWA1WA7e0I2RIQ",
+        session_tag_wasm(shared_secret).unwrap()
+    );
+}
+
+#[wasm_bindgen_test]
+fn verify_session_key() {
+    let shared_secret = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0-Pw";
+    let other = derive_key_wasm(shared_secret, "other", 64).unwrap();
+
+    assert!(verify_session_key_wasm(shared_secret, shared_secret).unwrap());
+    assert!(!verify_session_key_wasm(shared_secret, &other).unwrap());
+    assert!(verify_session_key_wasm(shared_secret, "not base64!").is_err());
+    assert!(verify_session_tag_wasm(shared_secret, "PonaeDioKWA1WA7e0I2RIQ").unwrap());
+    assert!(!verify_session_tag_wasm(&other, "PonaeDioKWA1WA7e0I2RIQ").unwrap());
+}
+
+#[wasm_bindgen_test]
+fn serialized_state() {
+    let state = client_login_wasm("my_pass", None).unwrap();
+    let serialized = state.serialize();
+    let restored = ClientStateLogin::deserialize(&serialized, &state.message()).unwrap();
+
+    assert_eq!(serialized, restored.serialize());
+    assert_eq!(state.message(), restored.message());
+    assert!(ClientStateLogin::deserialize("AAEC", "").is_err());
+}
+
+#[wasm_bindgen_test]
+fn worker_request_errors() {
+    let request = js_sys::Object::new();
+    js_sys::Reflect::set(&request, &"op".into(), &"client_login_finish".into()).unwrap();
+    js_sys::Reflect::set(&request, &"args".into(), &js_sys::Array::new()).unwrap();
+    assert!(handle_worker_request_wasm(JsValue::from(request.clone())).is_err());
+
+    js_sys::Reflect::set(&request, &"op".into(), &"server_login_finish".into()).unwrap();
+    assert!(handle_worker_request_wasm(JsValue::from(request)).is_err());
+}
+
+/// The test vectors of opaque-borink, regenerated with
+/// `cargo run --example test_vectors --features test-vectors`.
+#[wasm_bindgen_test]
+fn test_vectors() {
+    let file = js_sys::JSON::parse(include_str!("../../opaque-borink/test-vectors.json")).unwrap();
+    let vectors = js_sys::Array::from(&js_sys::Reflect::get(&file, &"vectors".into()).unwrap());
+    assert_eq!(3, vectors.length());
+
+    for vector in vectors.iter() {
+        let field = |name: &str| js_sys::Reflect::get(&vector, &name.into()).unwrap().as_string().unwrap();
+
+        let state = ClientStateLogin::deserialize(&field("login_client_state"), &field("login_request")).unwrap();
+        assert_eq!(field("login_request"), state.message());
+        let result = client_login_finish_wasm(
+            state,
+            &field("password"),
+            &field("login_response"),
+            Some(field("ksf_params")),
+            None,
+            None,
+            None,
+            Some(field("server_public_key")),
+        )
+        .unwrap();
+        assert_eq!(field("login_finish"), result.message);
+        assert_eq!(field("session_key"), result.shared_secret);
+        assert_eq!(field("export_key"), result.export_key);
+        assert_eq!(field("server_public_key"), result.server_public_key);
+    }
+}
+
+#[cfg(feature = "server")]
+#[wasm_bindgen_test]
+fn server_login() {
+    use opaquewasm::{
+        client_register_finish_wasm, client_register_wasm, create_setup_wasm, server_login_finish_wasm,
+        server_login_wasm, server_register_finish_wasm, server_register_wasm, setup_public_key_wasm,
+    };
+
+    let setup = create_setup_wasm(Some("p256_argon2".to_owned())).unwrap();
+    let state = client_register_wasm("my_pass", None, Some("p256_argon2".to_owned())).unwrap();
+    let response = server_register_wasm(&setup, &state.message(), "someperson").unwrap();
+    let upload = client_register_finish_wasm(state, "my_pass", &response, None, None).unwrap();
+    assert_eq!(setup_public_key_wasm(&setup).unwrap(), upload.server_public_key);
+    let password_file = server_register_finish_wasm(&setup, &upload.message).unwrap();
+
+    let state = client_login_wasm("my_pass", Some("p256_argon2".to_owned())).unwrap();
+    let server_result = server_login_wasm(&setup, Some(password_file), &state.message(), "someperson", None, None, None).unwrap();
+    let client_result = client_login_finish_wasm(state, "my_pass", &server_result.message, None, None, None, None, Some(setup_public_key_wasm(&setup).unwrap())).unwrap();
+    let seen_nonce = js_sys::Function::new_with_args("nonce", "if (this.has(nonce)) return false; this.add(nonce); return true;")
+        .bind(&js_sys::Set::new(&JsValue::UNDEFINED));
+    let finish_result = server_login_finish_wasm(&client_result.message, &server_result.state, Some(60), Some(seen_nonce.clone())).unwrap();
+    assert_eq!(client_result.shared_secret, finish_result.shared_secret);
+    assert_eq!(session_tag_wasm(&client_result.shared_secret).unwrap(), finish_result.session_tag);
+    // Replayed states are rejected, and seen nonces need a max age
+    assert!(server_login_finish_wasm(&client_result.message, &server_result.state, Some(60), Some(seen_nonce.clone())).is_err());
+    assert!(server_login_finish_wasm(&client_result.message, &server_result.state, None, Some(seen_nonce)).is_err());
+
+    // Unknown users get a response, but can't finish the login
+    let state = client_login_wasm("my_pass", Some("p256_argon2".to_owned())).unwrap();
+    let server_result = server_login_wasm(&setup, None, &state.message(), "otherperson", None, None, None).unwrap();
+    assert!(client_login_finish_wasm(state, "my_pass", &server_result.message, None, None, None, None, None).is_err());
+    assert!(server_login_finish_wasm(&client_result.message, "AAEC", None, None).is_err());
+}
+
+#[cfg(feature = "server")]
+#[wasm_bindgen_test]
+fn server_test_vectors() {
+    use opaquewasm::{
+        password_file_ksf_params_wasm, server_login_finish_wasm, server_register_finish_wasm, server_register_wasm,
+        setup_public_key_wasm,
+    };
+
+    let file = js_sys::JSON::parse(include_str!("../../opaque-borink/test-vectors.json")).unwrap();
+    let vectors = js_sys::Array::from(&js_sys::Reflect::get(&file, &"vectors".into()).unwrap());
+
+    for vector in vectors.iter() {
+        let field = |name: &str| js_sys::Reflect::get(&vector, &name.into()).unwrap().as_string().unwrap();
+
+        assert_eq!(field("server_public_key"), setup_public_key_wasm(&field("setup")).unwrap());
+        assert_eq!(field("register_response"), server_register_wasm(&field("setup"), &field("register_request"), &field("credential_id")).unwrap());
+        assert_eq!(field("password_file"), server_register_finish_wasm(&field("setup"), &field("register_upload")).unwrap());
+        assert_eq!(field("ksf_params"), password_file_ksf_params_wasm(&field("password_file")).unwrap());
+        let result = server_login_finish_wasm(&field("login_finish"), &field("login_server_state"), None, None).unwrap();
+        assert_eq!(field("session_key"), result.shared_secret);
+    }
+}